@@ -0,0 +1,551 @@
+//! Structural analysis of dataflow graphs.
+
+use crate::{Data, Function, Graph};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+/// Returned when an analysis requires the graph to be acyclic. Contains
+/// the name of a function on the cycle.
+#[derive(Debug)]
+pub struct CycleError(pub String);
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "graph contains a cycle through function '{}'", self.0)
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+/// Returns the enabled functions of `graph`, and for each the indices of the
+/// functions that consume one of its outputs. A function reading its own
+/// output, e.g. to update some state, doesn't count as depending on itself.
+pub fn function_dependencies(graph: &Graph) -> (Vec<&Function>, Vec<Vec<usize>>) {
+    let functions: Vec<&Function> = graph.functions.iter().filter(|f| f.is_enabled()).collect();
+
+    let mut consumers: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, f) in functions.iter().enumerate() {
+        for input in &f.inputs {
+            consumers.entry(&input.name).or_default().push(i);
+        }
+    }
+
+    let dependents = functions
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let mut ds: Vec<usize> = f.outputs
+                .iter()
+                .flat_map(|o| consumers.get(o.name.as_str()).into_iter().flatten())
+                .cloned()
+                .filter(|&d| d != i)
+                .collect();
+            ds.sort();
+            ds.dedup();
+            ds
+        })
+        .collect();
+
+    (functions, dependents)
+}
+
+/// Returns the names of the functions on a longest chain of functions in
+/// `graph`, where each function consumes an output of the one before.
+pub fn longest_chain(graph: &Graph) -> Result<Vec<String>, CycleError> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State { Unvisited, Visiting, Done }
+
+    // Computes the longest chain starting at each function, recording
+    // the next function on that chain.
+    fn visit(
+        i: usize,
+        functions: &[&Function],
+        dependents: &[Vec<usize>],
+        state: &mut [State],
+        best: &mut [(usize, Option<usize>)],
+    ) -> Result<(), CycleError> {
+        match state[i] {
+            State::Done => return Ok(()),
+            State::Visiting => return Err(CycleError(functions[i].name.clone())),
+            State::Unvisited => state[i] = State::Visiting,
+        }
+        best[i] = (1, None);
+        for &d in &dependents[i] {
+            visit(d, functions, dependents, state, best)?;
+            if best[d].0 + 1 > best[i].0 {
+                best[i] = (best[d].0 + 1, Some(d));
+            }
+        }
+        state[i] = State::Done;
+        Ok(())
+    }
+
+    let (functions, dependents) = function_dependencies(graph);
+    let mut state = vec![State::Unvisited; functions.len()];
+    let mut best = vec![(0, None); functions.len()];
+    for i in 0..functions.len() {
+        visit(i, &functions, &dependents, &mut state, &mut best)?;
+    }
+
+    let mut chain = Vec::new();
+    let mut next = (0..functions.len()).max_by_key(|&i| (best[i].0, std::cmp::Reverse(i)));
+    while let Some(i) = next {
+        chain.push(functions[i].name.clone());
+        next = best[i].1;
+    }
+    Ok(chain)
+}
+
+/// Returns the number of functions on a longest chain of functions in
+/// `graph`, i.e. the depth of the pipeline.
+pub fn longest_path_len(graph: &Graph) -> Result<usize, CycleError> {
+    longest_chain(graph).map(|chain| chain.len())
+}
+
+/// Returns the names of the enabled functions of `graph` in an order where
+/// each function comes after those whose outputs it consumes. Of the
+/// functions which could come next, the one with the first name is chosen.
+pub fn topological_order(graph: &Graph) -> Result<Vec<String>, CycleError> {
+    let (functions, dependents) = function_dependencies(graph);
+    let mut dependencies = vec![0; functions.len()];
+    for &d in dependents.iter().flatten() {
+        dependencies[d] += 1;
+    }
+
+    let mut ready: BTreeSet<(&str, usize)> = (0..functions.len())
+        .filter(|&i| dependencies[i] == 0)
+        .map(|i| (functions[i].name.as_str(), i))
+        .collect();
+    let mut order = Vec::new();
+    while let Some(&next) = ready.iter().next() {
+        ready.remove(&next);
+        let (name, i) = next;
+        order.push(name.to_string());
+        for &d in &dependents[i] {
+            dependencies[d] -= 1;
+            if dependencies[d] == 0 {
+                ready.insert((functions[d].name.as_str(), d));
+            }
+        }
+    }
+
+    if let Some(i) = (0..functions.len()).find(|&i| dependencies[i] > 0) {
+        return Err(CycleError(functions[i].name.clone()));
+    }
+    Ok(order)
+}
+
+#[test]
+fn longest_path_of_linear_chain() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: f, owner: o, inputs: [], outputs: [a]}
+  - {name: g, owner: o, inputs: [a], outputs: [b]}
+  - {name: h, owner: o, inputs: [b], outputs: [c]}
+"#).unwrap();
+
+    assert_eq!(longest_path_len(&graph).unwrap(), 3);
+}
+
+#[test]
+fn longest_path_of_branching_graph() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+  - {name: d, source: s}
+functions:
+  - {name: split, owner: o, inputs: [a], outputs: [b, c]}
+  - {name: short, owner: o, inputs: [b], outputs: []}
+  - {name: long, owner: o, inputs: [c], outputs: [d]}
+  - {name: tail, owner: o, inputs: [d], outputs: []}
+"#).unwrap();
+
+    assert_eq!(longest_path_len(&graph).unwrap(), 3);
+}
+
+#[test]
+fn longest_path_of_cycle() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+  - {name: g, owner: o, inputs: [b], outputs: [a]}
+"#).unwrap();
+
+    assert!(longest_path_len(&graph).is_err());
+}
+
+#[test]
+fn topological_order_breaks_ties_by_name() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: summarise, owner: o, inputs: [b, c], outputs: []}
+  - {name: load, owner: o, inputs: [a], outputs: [b]}
+  - {name: enrich, owner: o, inputs: [a], outputs: [c]}
+"#).unwrap();
+
+    assert_eq!(topological_order(&graph).unwrap(), vec!["enrich", "load", "summarise"]);
+}
+
+#[test]
+fn topological_order_of_cycle() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+  - {name: g, owner: o, inputs: [b], outputs: [a]}
+"#).unwrap();
+
+    assert!(topological_order(&graph).is_err());
+}
+
+/// Returns the pairs of distinct owners `(a, b)` such that an enabled
+/// function owned by `b` consumes data produced by one owned by `a`.
+pub fn owner_dependencies(graph: &Graph) -> BTreeSet<(&str, &str)> {
+    let functions = || graph.functions.iter().filter(|f| f.is_enabled());
+    let mut producers: HashMap<&str, Vec<&str>> = HashMap::new();
+    for f in functions() {
+        for output in &f.outputs {
+            producers.entry(&output.name).or_default().push(&f.owner);
+        }
+    }
+
+    let mut dependencies = BTreeSet::new();
+    for f in functions() {
+        for input in &f.inputs {
+            for &producer in producers.get(input.name.as_str()).into_iter().flatten() {
+                if producer != f.owner {
+                    dependencies.insert((producer, f.owner.as_str()));
+                }
+            }
+        }
+    }
+    dependencies
+}
+
+#[test]
+fn owner_dependencies_follow_data() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: raw, source: s}
+  - {name: clean, source: s}
+  - {name: report, source: s}
+functions:
+  - {name: ingest, owner: Ingest, inputs: [], outputs: [raw]}
+  - {name: cleanse, owner: Ingest, inputs: [raw], outputs: [clean]}
+  - {name: summarise, owner: Reporting, inputs: [clean], outputs: [report]}
+"#).unwrap();
+
+    let expected: BTreeSet<(&str, &str)> = vec![("Ingest", "Reporting")].into_iter().collect();
+    assert_eq!(owner_dependencies(&graph), expected);
+}
+
+/// Returns the pairs of distinct data `(a, b)` such that an enabled
+/// function consumes `a` and produces `b`, i.e. the lineage of each data.
+pub fn data_lineage(graph: &Graph) -> BTreeSet<(&str, &str)> {
+    graph
+        .functions
+        .iter()
+        .filter(|f| f.is_enabled())
+        .flat_map(|f| {
+            f.inputs
+                .iter()
+                .flat_map(move |i| f.outputs.iter().map(move |o| (i.name.as_str(), o.name.as_str())))
+        })
+        .filter(|(a, b)| a != b)
+        .collect()
+}
+
+#[test]
+fn data_lineage_through_functions() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+  - {name: g, owner: o, inputs: [b], outputs: [b, c]}
+"#).unwrap();
+
+    let expected: BTreeSet<(&str, &str)> = vec![("a", "b"), ("b", "c")].into_iter().collect();
+    assert_eq!(data_lineage(&graph), expected);
+}
+
+/// Returns the data consumed but not produced by the enabled functions
+/// of `graph`, i.e. the inputs to the system from outside, in the order
+/// in which they're first consumed.
+pub fn external_inputs(graph: &Graph) -> Vec<String> {
+    let functions = || graph.functions.iter().filter(|f| f.is_enabled());
+    let produced: HashSet<&str> = functions()
+        .flat_map(|f| f.outputs.iter().map(|o| o.name.as_str()))
+        .collect();
+
+    let mut seen = HashSet::new();
+    functions()
+        .flat_map(|f| f.inputs.iter().map(|i| i.name.as_str()))
+        .filter(|i| !produced.contains(i) && seen.insert(*i))
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the data produced but not consumed by the enabled functions
+/// of `graph`, in the order in which they're first produced.
+pub fn terminal_outputs(graph: &Graph) -> Vec<String> {
+    let functions = || graph.functions.iter().filter(|f| f.is_enabled());
+    let consumed: HashSet<&str> = functions()
+        .flat_map(|f| f.inputs.iter().map(|i| i.name.as_str()))
+        .collect();
+
+    let mut seen = HashSet::new();
+    functions()
+        .flat_map(|f| f.outputs.iter().map(|o| o.name.as_str()))
+        .filter(|o| !consumed.contains(o) && seen.insert(*o))
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the number of flows into or out of each of `data` and
+/// `functions`, keyed by name.
+pub fn degrees<'a>(data: &'a [Data], functions: &[&'a Function]) -> HashMap<&'a str, usize> {
+    let mut degrees: HashMap<&str, usize> = data.iter().map(|d| (d.name.as_str(), 0)).collect();
+    for f in functions {
+        for flow in f.inputs.iter().chain(&f.outputs) {
+            *degrees.entry(&flow.name).or_default() += 1;
+        }
+        *degrees.entry(&f.name).or_default() += f.inputs.len() + f.outputs.len();
+    }
+    degrees
+}
+
+#[test]
+fn degrees_count_flows() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: unused, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+  - {name: g, owner: o, inputs: [a, b], outputs: []}
+"#).unwrap();
+
+    let functions: Vec<&Function> = graph.functions.iter().collect();
+    let degrees = degrees(&graph.data, &functions);
+    assert_eq!(degrees["a"], 2);
+    assert_eq!(degrees["b"], 2);
+    assert_eq!(degrees["unused"], 0);
+    assert_eq!(degrees["f"], 2);
+    assert_eq!(degrees["g"], 2);
+}
+
+/// Partitions `data` and `functions` into weakly connected components,
+/// i.e. sets of nodes joined by flows regardless of their direction.
+/// Components are ordered by their first node, and list the names of
+/// their data and then their functions, each in the order given.
+pub fn components<'a>(data: &'a [Data], functions: &[&'a Function]) -> Vec<Vec<&'a str>> {
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    // Data take indices 0..data.len(), followed by the functions.
+    let mut parent: Vec<usize> = (0..data.len() + functions.len()).collect();
+    let index: HashMap<&str, usize> = data.iter().enumerate().map(|(i, d)| (d.name.as_str(), i)).collect();
+    for (i, f) in functions.iter().enumerate() {
+        for flow in f.inputs.iter().chain(&f.outputs) {
+            if let Some(&d) = index.get(flow.name.as_str()) {
+                let (a, b) = (find(&mut parent, d), find(&mut parent, data.len() + i));
+                parent[a] = b;
+            }
+        }
+    }
+
+    let names = data.iter().map(|d| d.name.as_str()).chain(functions.iter().map(|f| f.name.as_str()));
+    let mut components: Vec<Vec<&str>> = Vec::new();
+    let mut component_of_root: HashMap<usize, usize> = HashMap::new();
+    for (i, name) in names.enumerate() {
+        let root = find(&mut parent, i);
+        let c = *component_of_root.entry(root).or_insert_with(|| {
+            components.push(Vec::new());
+            components.len() - 1
+        });
+        components[c].push(name);
+    }
+    components
+}
+
+/// Counts the simple paths from the node named `from` to the node named `to`
+/// along the flows of the enabled functions of `graph`, where nodes are both
+/// data and functions. Counting stops at `cap`, as the number of paths can
+/// grow exponentially with the size of the graph, and the second value
+/// returned is true if there are more paths than that.
+pub fn count_paths(graph: &Graph, from: &str, to: &str, cap: usize) -> (usize, bool) {
+    fn visit<'a>(
+        node: &'a str,
+        to: &str,
+        successors: &HashMap<&'a str, Vec<&'a str>>,
+        on_path: &mut HashSet<&'a str>,
+        count: &mut usize,
+        cap: usize,
+    ) {
+        if *count >= cap {
+            return;
+        }
+        if node == to {
+            *count += 1;
+            return;
+        }
+        on_path.insert(node);
+        for &next in successors.get(node).into_iter().flatten() {
+            if !on_path.contains(next) {
+                visit(next, to, successors, on_path, count, cap);
+            }
+        }
+        on_path.remove(node);
+    }
+
+    let mut successors: HashMap<&str, Vec<&str>> = HashMap::new();
+    for f in graph.functions.iter().filter(|f| f.is_enabled()) {
+        for input in &f.inputs {
+            successors.entry(&input.name).or_default().push(&f.name);
+        }
+        for output in &f.outputs {
+            successors.entry(&f.name).or_default().push(&output.name);
+        }
+    }
+
+    // Looking for one path beyond the cap tells whether it was reached.
+    let mut count = 0;
+    visit(from, to, &successors, &mut HashSet::new(), &mut count, cap + 1);
+    (count.min(cap), count > cap)
+}
+
+#[test]
+fn count_single_path() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+  - {name: g, owner: o, inputs: [b], outputs: [b]}
+"#).unwrap();
+
+    assert_eq!(count_paths(&graph, "a", "b", 10), (1, false));
+    assert_eq!(count_paths(&graph, "b", "a", 10), (0, false));
+}
+
+#[cfg(test)]
+const DIAMOND: &str = r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+  - {name: d, source: s}
+functions:
+  - {name: split, owner: o, inputs: [a], outputs: [b, c]}
+  - {name: left, owner: o, inputs: [b], outputs: [d]}
+  - {name: right, owner: o, inputs: [c], outputs: [d]}
+"#;
+
+#[test]
+fn count_diamond_paths() {
+    let graph = crate::parse_graph(DIAMOND).unwrap();
+    assert_eq!(count_paths(&graph, "a", "d", 10), (2, false));
+    assert_eq!(count_paths(&graph, "split", "left", 10), (1, false));
+}
+
+#[test]
+fn count_paths_stops_at_cap() {
+    let graph = crate::parse_graph(DIAMOND).unwrap();
+    assert_eq!(count_paths(&graph, "a", "d", 1), (1, true));
+    assert_eq!(count_paths(&graph, "a", "d", 2), (2, false));
+}
+
+#[test]
+fn components_ignore_flow_direction() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: x, source: s}
+  - {name: b, source: s}
+  - {name: y, source: s}
+  - {name: lonely, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+  - {name: g, owner: o, inputs: [x], outputs: [y]}
+  - {name: h, owner: o, inputs: [], outputs: [b]}
+"#).unwrap();
+    let functions: Vec<&Function> = graph.functions.iter().collect();
+
+    assert_eq!(components(&graph.data, &functions), vec![
+        vec!["a", "b", "f", "h"],
+        vec!["x", "y", "g"],
+        vec!["lonely"],
+    ]);
+}
+
+#[test]
+fn external_inputs_are_consumed_but_not_produced() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: clicks, source: Browser}
+  - {name: sessions, source: Pipeline}
+  - {name: config, source: Ops}
+functions:
+  - {name: sessionise, owner: Pipeline, inputs: [clicks, config], outputs: [sessions]}
+  - {name: report, owner: Pipeline, inputs: [sessions, config], outputs: []}
+"#).unwrap();
+
+    assert_eq!(external_inputs(&graph), vec!["clicks", "config"]);
+}
+
+#[test]
+fn longest_chain_of_pipeline() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: raw, source: Upstream}
+  - {name: clean, source: Pipeline}
+  - {name: enriched, source: Pipeline}
+  - {name: state, source: Pipeline}
+functions:
+  - {name: report, owner: Pipeline, inputs: [clean], outputs: []}
+  - {name: enrich, owner: Pipeline, inputs: [clean, state], outputs: [enriched, state]}
+  - {name: clean, owner: Pipeline, inputs: [raw], outputs: [clean]}
+  - {name: store, owner: Pipeline, inputs: [enriched], outputs: []}
+"#).unwrap();
+
+    assert_eq!(longest_chain(&graph).unwrap(), vec!["clean", "enrich", "store"]);
+}
+
+#[test]
+fn longest_chain_of_cycle() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: Pipeline}
+  - {name: b, source: Pipeline}
+functions:
+  - {name: f, owner: Pipeline, inputs: [a], outputs: [b]}
+  - {name: g, owner: Pipeline, inputs: [b], outputs: [a]}
+"#).unwrap();
+
+    assert!(longest_chain(&graph).is_err());
+}
@@ -0,0 +1,80 @@
+//! Caching of parsed specs, so that a spec rendered several times in one
+//! run is only parsed again when its content changes.
+
+use crate::Graph;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use failure::ResultExt;
+
+/// Parsed specs, keyed by path and the hash of the content they were
+/// parsed from.
+#[derive(Default)]
+pub struct SpecCache {
+    entries: HashMap<PathBuf, (u64, Rc<Graph>)>,
+}
+
+impl SpecCache {
+    pub fn new() -> SpecCache {
+        SpecCache::default()
+    }
+
+    /// Reads and parses the spec at `path` as `read_graph` does, unless the
+    /// file's content is unchanged since an earlier call. Specs read from
+    /// stdin or URLs are never cached.
+    pub fn load(&mut self, path: &Path) -> Result<Rc<Graph>, failure::Error> {
+        let is_file = path.as_os_str() != "-"
+            && !path.to_str().is_some_and(|p| p.starts_with("http://") || p.starts_with("https://"));
+        if !is_file {
+            return Ok(Rc::new(crate::read_graph(path)?));
+        }
+
+        // The file is read once to hash it and again to parse it, rather
+        // than held in memory, as specs can be large.
+        let hash = hash_file(path)?;
+        if let Some((cached_hash, graph)) = self.entries.get(path) {
+            if *cached_hash == hash {
+                return Ok(graph.clone());
+            }
+        }
+        let graph = Rc::new(crate::read_graph(path)?);
+        self.entries.insert(path.to_path_buf(), (hash, graph.clone()));
+        Ok(graph)
+    }
+}
+
+fn hash_file(path: &Path) -> Result<u64, failure::Error> {
+    let mut file = std::fs::File::open(path)
+        .with_context(|_| format!("could not read file '{:?}'", path))?;
+    let mut hasher = DefaultHasher::new();
+    let mut buf = [0; 8192];
+    loop {
+        let n = file.read(&mut buf)
+            .with_context(|_| format!("could not read file '{:?}'", path))?;
+        if n == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buf[..n]);
+    }
+}
+
+#[test]
+fn unchanged_specs_are_reused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("spec.yaml");
+    std::fs::write(&path, include_str!("sample.yaml")).unwrap();
+    let mut cache = SpecCache::new();
+
+    let first = cache.load(&path).unwrap();
+    let second = cache.load(&path).unwrap();
+    assert!(Rc::ptr_eq(&first, &second));
+
+    std::fs::write(&path, "data: []\nfunctions: []\n").unwrap();
+    let third = cache.load(&path).unwrap();
+    assert!(!Rc::ptr_eq(&first, &third));
+    assert!(third.data.is_empty());
+}
@@ -0,0 +1,783 @@
+//! Checks for specs that parse successfully but are likely to
+//! render incorrectly or indicate a mistake by the author.
+
+use crate::{Flow, Graph, Role};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+/// Returned when a spec is inconsistent, e.g. when a function refers to
+/// data it doesn't define, so that it can't be rendered.
+#[derive(Debug)]
+pub struct ValidationError(pub String);
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Returned when a check fails in strict mode.
+#[derive(Debug)]
+pub struct LintError(pub String);
+
+impl fmt::Display for LintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for LintError {}
+
+/// Checks that every data and function has a name, and every function an
+/// owner, as blank names would produce empty node ids and corrupt DOT.
+pub fn empty_names(graph: &Graph) -> Result<(), ValidationError> {
+    let mut problems = Vec::new();
+    for (i, d) in graph.data.iter().enumerate() {
+        if d.name.trim().is_empty() {
+            problems.push(format!("data[{}] has an empty name", i));
+        }
+    }
+    for (i, f) in graph.functions.iter().enumerate() {
+        if f.name.trim().is_empty() {
+            problems.push(format!("functions[{}] has an empty name", i));
+        }
+        if f.owner.trim().is_empty() {
+            problems.push(format!("functions[{}] has an empty owner", i));
+        }
+    }
+    if !problems.is_empty() {
+        return Err(ValidationError(problems.join(", ")));
+    }
+    Ok(())
+}
+
+#[test]
+fn empty_data_name_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: "", source: s}
+functions: []
+"#).unwrap();
+
+    assert_eq!(empty_names(&graph).unwrap_err().to_string(), "data[1] has an empty name");
+}
+
+#[test]
+fn empty_function_owner_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: " ", inputs: [a], outputs: []}
+"#).unwrap();
+
+    assert_eq!(empty_names(&graph).unwrap_err().to_string(), "functions[0] has an empty owner");
+}
+
+/// Checks that every data has a source. Blank sources are allowed by
+/// default, e.g. for data from outside the organisation, but can be
+/// rejected with --strict-schema.
+pub fn empty_sources(graph: &Graph) -> Result<(), ValidationError> {
+    let problems: Vec<String> = graph.data
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| d.source.trim().is_empty())
+        .map(|(i, d)| format!("data[{}] ({}) has an empty source", i, d.name))
+        .collect();
+    if !problems.is_empty() {
+        return Err(ValidationError(problems.join(", ")));
+    }
+    Ok(())
+}
+
+#[test]
+fn empty_data_source_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: "  "}
+functions: []
+"#).unwrap();
+
+    assert_eq!(empty_sources(&graph).unwrap_err().to_string(), "data[1] (b) has an empty source");
+}
+
+/// Checks that every input and output of a function is a defined data.
+pub fn undefined_data(graph: &Graph) -> Result<(), ValidationError> {
+    let data: HashSet<&str> = graph.data.iter().map(|d| d.name.as_str()).collect();
+    let missing: Vec<String> = graph.functions
+        .iter()
+        .flat_map(|f| f.inputs.iter().chain(&f.outputs).map(move |flow| (f, flow)))
+        .filter(|(_, flow)| !data.contains(flow.name.as_str()))
+        .map(|(f, flow)| format!("{} (used by {})", flow.name, f.name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(ValidationError(format!("undefined data: {}", missing.join(", "))));
+    }
+    Ok(())
+}
+
+#[test]
+fn undefined_data_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a, b], outputs: [c]}
+"#).unwrap();
+
+    assert_eq!(
+        undefined_data(&graph).unwrap_err().to_string(),
+        "undefined data: b (used by f), c (used by f)"
+    );
+}
+
+/// Checks that every note is near a defined data or function, if any.
+pub fn undefined_note_targets(graph: &Graph) -> Result<(), ValidationError> {
+    let missing: Vec<&str> = graph.notes
+        .iter()
+        .filter_map(|note| note.near.as_deref())
+        .filter(|near| {
+            !graph.data.iter().any(|d| d.name == *near) && !graph.functions.iter().any(|f| f.name == *near)
+        })
+        .collect();
+    if !missing.is_empty() {
+        return Err(ValidationError(format!("notes near undefined nodes: {}", missing.join(", "))));
+    }
+    Ok(())
+}
+
+#[test]
+fn undefined_note_targets_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: []}
+notes:
+  - {text: "Runs nightly", near: f}
+  - {text: "Legacy", near: g}
+  - {text: "Owned by the data team"}
+"#).unwrap();
+
+    assert_eq!(undefined_note_targets(&graph).unwrap_err().to_string(), "notes near undefined nodes: g");
+}
+
+/// Checks that no function lists the same input, or the same output, more
+/// than once, even if the flows are annotated differently, e.g. with
+/// different weights. Flows of the same data to different ports are allowed.
+pub fn duplicate_flows(graph: &Graph) -> Result<(), ValidationError> {
+    let mut duplicates = Vec::new();
+    for f in &graph.functions {
+        for (kind, flows) in &[("input", &f.inputs), ("output", &f.outputs)] {
+            for (i, flow) in flows.iter().enumerate() {
+                // Each duplicate is reported at its second occurrence.
+                let same = |earlier: &&Flow| (&earlier.name, &earlier.port) == (&flow.name, &flow.port);
+                if flows[..i].iter().filter(same).count() == 1 {
+                    duplicates.push(format!("{} lists {} {} more than once", f.name, kind, flow.name));
+                }
+            }
+        }
+    }
+    if !duplicates.is_empty() {
+        return Err(ValidationError(format!("duplicate flows: {}", duplicates.join(", "))));
+    }
+    Ok(())
+}
+
+#[test]
+fn duplicate_flows_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a, b, a, a], outputs: [b]}
+  - {name: g, owner: o, inputs: [{name: a, port: x}, {name: a, port: y}], outputs: []}
+  - {name: h, owner: o, inputs: [a, {name: a, note: x}], outputs: [{name: b, weight: 2}, {name: b, format: csv}]}
+"#).unwrap();
+
+    assert_eq!(
+        duplicate_flows(&graph).unwrap_err().to_string(),
+        "duplicate flows: f lists input a more than once, \
+         h lists input a more than once, h lists output b more than once"
+    );
+}
+
+/// Returns a description of each name given to more than one function,
+/// with their owners, or to more than one data, with their sources, e.g.
+/// "load (owners Billing, Search)". Functions come before data, and each
+/// are in alphabetical order.
+pub fn duplicate_names(graph: &Graph) -> Vec<String> {
+    let mut owners: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for f in &graph.functions {
+        owners.entry(&f.name).or_default().push(&f.owner);
+    }
+    let mut sources: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for d in &graph.data {
+        sources.entry(&d.name).or_default().push(&d.source);
+    }
+    let describe = |kind: &str, names: BTreeMap<&str, Vec<&str>>| -> Vec<String> {
+        names.into_iter()
+            .filter(|(_, values)| values.len() > 1)
+            .map(|(name, values)| format!("{} ({} {})", name, kind, values.join(", ")))
+            .collect()
+    };
+    let mut duplicates = describe("owners", owners);
+    duplicates.extend(describe("sources", sources));
+    duplicates
+}
+
+#[test]
+fn duplicate_names_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: Shop}
+  - {name: b, source: Shop}
+functions:
+  - {name: load, owner: Search, inputs: [a], outputs: []}
+  - {name: load, owner: Billing, inputs: [a], outputs: [b]}
+  - {name: index, owner: Search, inputs: [b], outputs: []}
+"#).unwrap();
+
+    assert_eq!(duplicate_names(&graph), vec!["load (owners Search, Billing)", "b (sources Shop, Shop)"]);
+}
+
+/// Returns the names used by both a data and a function. These would
+/// be rendered as a single DOT node, with nonsensical edges.
+pub fn data_function_name_clash(graph: &Graph) -> Vec<String> {
+    let data: HashSet<&str> = graph.data.iter().map(|d| d.name.as_str()).collect();
+    let mut clashes: Vec<String> = graph
+        .functions
+        .iter()
+        .filter(|f| data.contains(f.name.as_str()))
+        .map(|f| f.name.clone())
+        .collect();
+    clashes.sort();
+    clashes.dedup();
+    clashes
+}
+
+/// Returns a description of each conflict between data with the same
+/// name but different sources or descriptions, e.g. from merging specs
+/// written by different teams.
+pub fn conflicting_data(graph: &Graph) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for (i, a) in graph.data.iter().enumerate() {
+        // Only the first declaration of each name is compared against the
+        // others, so that each conflict is reported once.
+        if graph.data[..i].iter().any(|d| d.name == a.name) {
+            continue;
+        }
+        for b in graph.data[i + 1..].iter().filter(|d| d.name == a.name) {
+            if a.source != b.source {
+                conflicts.push(format!("{} has source '{}' and '{}'", a.name, a.source, b.source));
+            }
+            if a.description != b.description {
+                conflicts.push(format!(
+                    "{} has description '{}' and '{}'",
+                    a.name,
+                    a.description.as_deref().unwrap_or(""),
+                    b.description.as_deref().unwrap_or("")
+                ));
+            }
+        }
+    }
+    conflicts
+}
+
+#[test]
+fn conflicting_data_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: users, source: Accounts, description: Registered users}
+  - {name: orders, source: Shop}
+  - {name: users, source: Marketing, description: Registered users}
+  - {name: orders, source: Shop}
+functions: []
+"#).unwrap();
+
+    assert_eq!(conflicting_data(&graph), vec!["users has source 'Accounts' and 'Marketing'"]);
+
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: users, source: Accounts, description: Registered users}
+  - {name: users, source: Accounts}
+functions: []
+"#).unwrap();
+
+    assert_eq!(conflicting_data(&graph), vec!["users has description 'Registered users' and ''"]);
+}
+
+/// Returns the names of functions which read one of their own outputs,
+/// which renders as a confusing loop between the function and the data.
+pub fn self_dependencies(graph: &Graph) -> Vec<String> {
+    graph
+        .functions
+        .iter()
+        .filter(|f| f.outputs.iter().any(|o| f.inputs.iter().any(|i| i.name == o.name)))
+        .map(|f| f.name.clone())
+        .collect()
+}
+
+/// Returns the data which are produced but never consumed, other than those
+/// marked as sinks. These may be dead ends left over from a refactoring.
+pub fn dead_outputs(graph: &Graph) -> Vec<String> {
+    let sinks: HashSet<&str> = graph
+        .data
+        .iter()
+        .filter(|d| d.role == Some(Role::Sink))
+        .map(|d| d.name.as_str())
+        .collect();
+
+    crate::analysis::terminal_outputs(graph)
+        .into_iter()
+        .filter(|d| !sinks.contains(d.as_str()))
+        .collect()
+}
+
+/// Returns the data which no function consumes or produces. These are
+/// drawn as disconnected nodes, and are usually either stale or missing
+/// the function which should use them.
+pub fn orphans(graph: &Graph) -> Vec<String> {
+    let used: HashSet<&str> = graph
+        .functions
+        .iter()
+        .flat_map(|f| f.inputs.iter().chain(&f.outputs))
+        .map(|flow| flow.name.as_str())
+        .collect();
+    graph
+        .data
+        .iter()
+        .filter(|d| !used.contains(d.name.as_str()))
+        .map(|d| d.name.clone())
+        .collect()
+}
+
+/// Returns the functions of `graph` whose owner isn't in `allowed`, each
+/// with its owner, e.g. to catch misspelt owners.
+pub fn unlisted_owners(graph: &Graph, allowed: &HashSet<String>) -> Vec<(String, String)> {
+    graph
+        .functions
+        .iter()
+        .filter(|f| !allowed.contains(&f.owner))
+        .map(|f| (f.name.clone(), f.owner.clone()))
+        .collect()
+}
+
+/// Returns a description of each owner or source in `graph` which looks
+/// like a placeholder: one of `placeholders`, ignoring case and anything
+/// after a following punctuation mark, e.g. "todo: ask billing", or an
+/// unexpanded variable such as "${TEAM}".
+pub fn placeholders(graph: &Graph, placeholders: &[String]) -> Vec<String> {
+    let is_placeholder = |value: &str| {
+        let value = value.trim();
+        let unexpanded = value.find("${").is_some_and(|start| value[start..].contains('}'));
+        unexpanded || placeholders.iter().filter(|p| !p.is_empty()).any(|p| {
+            value.len() >= p.len()
+                && value.is_char_boundary(p.len())
+                && value[..p.len()].eq_ignore_ascii_case(p)
+                && !value[p.len()..].starts_with(|c: char| c.is_alphanumeric())
+        })
+    };
+    let functions = graph.functions
+        .iter()
+        .filter(|f| is_placeholder(&f.owner))
+        .map(|f| format!("{} (owner '{}')", f.name, f.owner));
+    let data = graph.data
+        .iter()
+        .filter(|d| is_placeholder(&d.source))
+        .map(|d| format!("{} (source '{}')", d.name, d.source));
+    functions.chain(data).collect()
+}
+
+#[test]
+fn placeholders_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: "${SOURCE_TEAM}"}
+  - {name: b, source: Todoist}
+functions:
+  - {name: f, owner: TODO, inputs: [a], outputs: [b]}
+  - {name: g, owner: "fixme: ask in chat", inputs: [b], outputs: []}
+  - {name: h, owner: Billing, inputs: [b], outputs: []}
+  - {name: i, owner: "???", inputs: [b], outputs: []}
+"#).unwrap();
+
+    let defaults: Vec<String> = vec!["TODO".into(), "FIXME".into(), "???".into()];
+    assert_eq!(placeholders(&graph, &defaults), vec![
+        "f (owner 'TODO')",
+        "g (owner 'fixme: ask in chat')",
+        "i (owner '???')",
+        "a (source '${SOURCE_TEAM}')",
+    ]);
+    assert_eq!(placeholders(&graph, &["Billing".to_string()]), vec![
+        "h (owner 'Billing')",
+        "a (source '${SOURCE_TEAM}')",
+    ]);
+}
+
+/// Returns the data which are shared mutable state, i.e. either written by
+/// two or more functions and read by at least one, or written by at least
+/// one function and both read and written by two or more. Concurrent
+/// writers of these need some coordination.
+pub fn shared_mutable_data(graph: &Graph) -> Vec<String> {
+    graph
+        .data
+        .iter()
+        .filter(|d| {
+            let producers = producers(graph, &d.name);
+            let consumers: HashSet<&str> = graph.functions
+                .iter()
+                .filter(|f| f.inputs.iter().any(|i| i.name == d.name))
+                .map(|f| f.name.as_str())
+                .collect();
+            let read_writers = producers.intersection(&consumers).count();
+            (producers.len() >= 2 && !consumers.is_empty())
+                || (!producers.is_empty() && read_writers >= 2)
+        })
+        .map(|d| d.name.clone())
+        .collect()
+}
+
+/// Returns the names of the functions producing the data `name`.
+fn producers<'a>(graph: &'a Graph, name: &str) -> HashSet<&'a str> {
+    graph.functions
+        .iter()
+        .filter(|f| f.outputs.iter().any(|o| o.name == name))
+        .map(|f| f.name.as_str())
+        .collect()
+}
+
+/// Returns the data, in alphabetical order, which are produced by a
+/// function in one of `fragments` but only read, as if external inputs, in
+/// another. This is expected when fragments are wired together, but can
+/// also mean that a fragment's authors don't know where its inputs come
+/// from.
+pub fn mixed_roles(fragments: &[Graph]) -> Vec<String> {
+    let outputs = |graph: &Graph| -> HashSet<String> {
+        graph.functions.iter().flat_map(|f| &f.outputs).map(|o| o.name.clone()).collect()
+    };
+    let produced: HashSet<String> = fragments.iter().flat_map(outputs).collect();
+    let mut data: Vec<String> = fragments
+        .iter()
+        .flat_map(|graph| {
+            let outputs = outputs(graph);
+            graph.functions
+                .iter()
+                .flat_map(|f| &f.inputs)
+                .map(|i| i.name.clone())
+                .filter(|name| !outputs.contains(name) && produced.contains(name))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    data.sort();
+    data.dedup();
+    data
+}
+
+/// Returns each data produced by more than one function, with the names
+/// of those functions in alphabetical order.
+pub fn multiple_producers(graph: &Graph) -> Vec<(String, Vec<String>)> {
+    graph
+        .data
+        .iter()
+        .filter_map(|d| {
+            let mut producers: Vec<String> = producers(graph, &d.name).into_iter().map(String::from).collect();
+            producers.sort();
+            if producers.len() > 1 {
+                Some((d.name.clone(), producers))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Returns the data which no function produces, i.e. external inputs and
+/// orphans.
+pub fn unproduced(graph: &Graph) -> Vec<String> {
+    graph
+        .data
+        .iter()
+        .filter(|d| producers(graph, &d.name).is_empty())
+        .map(|d| d.name.clone())
+        .collect()
+}
+
+/// Returns the pairs of distinct data or function names within
+/// `max_distance` edits of each other, which are likely to be typos.
+pub fn similar_names(graph: &Graph, max_distance: usize) -> Vec<(String, String)> {
+    let mut names: Vec<&str> = graph
+        .data
+        .iter()
+        .map(|d| d.name.as_str())
+        .chain(graph.functions.iter().map(|f| f.name.as_str()))
+        .chain(graph.functions.iter().flat_map(|f| f.inputs.iter().chain(&f.outputs)).map(|d| d.name.as_str()))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let mut pairs = Vec::new();
+    for (i, a) in names.iter().enumerate() {
+        for b in &names[i + 1..] {
+            if edit_distance(a, b) <= max_distance {
+                pairs.push((a.to_string(), b.to_string()));
+            }
+        }
+    }
+    pairs
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[test]
+fn edit_distances() {
+    assert_eq!(edit_distance("customer_id", "customerid"), 1);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("same", "same"), 0);
+}
+
+#[test]
+fn similar_names_reported() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: customer_id, source: Accounts}
+  - {name: orders, source: Shop}
+functions:
+  - {name: invoice, owner: Billing, inputs: [customerid, orders], outputs: []}
+"#).unwrap();
+
+    assert_eq!(
+        similar_names(&graph, 1),
+        vec![("customer_id".to_string(), "customerid".to_string())]
+    );
+}
+
+#[test]
+fn dead_outputs_exclude_sinks() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: orders, source: Shop}
+  - {name: invoices, source: Billing, role: sink}
+  - {name: legacy_export, source: Billing}
+functions:
+  - {name: bill, owner: Billing, inputs: [orders], outputs: [invoices, legacy_export]}
+"#).unwrap();
+
+    assert_eq!(crate::analysis::terminal_outputs(&graph), vec!["invoices", "legacy_export"]);
+    assert_eq!(dead_outputs(&graph), vec!["legacy_export"]);
+}
+
+#[test]
+fn shared_mutable_data_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: config, source: Ops}
+  - {name: balances, source: Billing}
+functions:
+  - {name: charge, owner: Billing, inputs: [config, balances], outputs: [balances]}
+  - {name: refund, owner: Billing, inputs: [config, balances], outputs: [balances]}
+  - {name: report, owner: Finance, inputs: [config, balances], outputs: []}
+  - {name: publish, owner: Ops, inputs: [], outputs: [config]}
+"#).unwrap();
+
+    // config is written once and only read by the others, so is safe.
+    assert_eq!(shared_mutable_data(&graph), vec!["balances"]);
+}
+
+#[test]
+fn mixed_roles_across_fragments() {
+    let ingest = crate::parse_graph(r#"
+data:
+  - {name: events, source: Web}
+  - {name: sessions, source: Ingest}
+functions:
+  - {name: sessionise, owner: Ingest, inputs: [events], outputs: [sessions]}
+"#).unwrap();
+    let report = crate::parse_graph(r#"
+data:
+  - {name: events, source: Web}
+  - {name: sessions, source: Ingest}
+  - {name: report, source: Reporting}
+functions:
+  - {name: summarise, owner: Reporting, inputs: [sessions, events], outputs: [report]}
+"#).unwrap();
+
+    assert_eq!(mixed_roles(&[ingest.clone(), report]), vec!["sessions"]);
+    assert!(mixed_roles(&[ingest]).is_empty());
+}
+
+#[test]
+fn producers_counted() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: orders, source: Shop}
+  - {name: invoices, source: Billing}
+  - {name: ledger, source: Finance}
+functions:
+  - {name: bill, owner: Billing, inputs: [orders], outputs: [invoices, ledger]}
+  - {name: refund, owner: Billing, inputs: [orders], outputs: [ledger]}
+"#).unwrap();
+
+    assert_eq!(
+        multiple_producers(&graph),
+        vec![("ledger".to_string(), vec!["bill".to_string(), "refund".to_string()])]
+    );
+    assert_eq!(unproduced(&graph), vec!["orders"]);
+}
+
+#[test]
+fn orphans_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+"#).unwrap();
+
+    assert_eq!(orphans(&graph), vec!["c"]);
+}
+
+#[test]
+fn unlisted_owners_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: Billing, inputs: [a], outputs: []}
+  - {name: g, owner: Biling, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let allowed = crate::filter::parse_allowlist("# approved owners\nBilling\n\nShipping\n");
+    assert_eq!(unlisted_owners(&graph, &allowed), vec![("g".to_string(), "Biling".to_string())]);
+}
+
+#[test]
+fn self_dependencies_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: events, source: Player}
+  - {name: state, source: Game}
+  - {name: frame, source: Game}
+functions:
+  - {name: update, owner: Game, inputs: [events, state], outputs: [state]}
+  - {name: draw, owner: Game, inputs: [state], outputs: [frame]}
+"#).unwrap();
+
+    assert_eq!(self_dependencies(&graph), vec!["update"]);
+}
+
+#[test]
+fn data_function_name_clash_detected() {
+    let graph = crate::parse_graph(r#"
+data:
+  - name: events
+    source: Player
+  - name: position
+    source: GameLoop
+functions:
+  - name: events
+    owner: Game
+    inputs: [position]
+    outputs: []
+  - name: update
+    owner: Game
+    inputs: [events]
+    outputs: [position]
+"#).unwrap();
+
+    assert_eq!(data_function_name_clash(&graph), vec!["events"]);
+}
+
+/// Checks that no chain of functions, each consuming an output of the one
+/// before, contains more than `max_depth` functions.
+pub fn max_depth(graph: &Graph, max_depth: usize) -> Result<(), failure::Error> {
+    let chain = crate::analysis::longest_chain(graph)?;
+    if chain.len() > max_depth {
+        return Err(LintError(format!(
+            "chain of {} functions exceeds maximum depth of {}: {}",
+            chain.len(),
+            max_depth,
+            chain.join(" -> ")
+        )).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+const CHAIN: &str = r#"
+data:
+  - {name: a, source: Upstream}
+  - {name: b, source: Pipeline}
+  - {name: c, source: Pipeline}
+  - {name: d, source: Pipeline}
+functions:
+  - {name: f, owner: Pipeline, inputs: [a], outputs: [b]}
+  - {name: g, owner: Pipeline, inputs: [b], outputs: [c]}
+  - {name: h, owner: Pipeline, inputs: [c], outputs: [d]}
+"#;
+
+#[test]
+fn max_depth_passes_for_shallow_graph() {
+    let graph = crate::parse_graph(CHAIN).unwrap();
+    assert!(max_depth(&graph, 3).is_ok());
+}
+
+#[test]
+fn max_depth_fails_for_deep_chain() {
+    let graph = crate::parse_graph(CHAIN).unwrap();
+    let err = max_depth(&graph, 2).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "chain of 3 functions exceeds maximum depth of 2: f -> g -> h"
+    );
+}
+
+/// Checks that rendering `graph` would draw no more than `max_edges` edges,
+/// as Graphviz becomes very slow on large diagrams. Disabled functions'
+/// edges are only counted if `show_disabled` is set.
+pub fn max_edges(graph: &Graph, max_edges: usize, show_disabled: bool) -> Result<(), failure::Error> {
+    let edges: usize = graph.functions
+        .iter()
+        .filter(|f| show_disabled || f.is_enabled())
+        .map(|f| f.inputs.len() + f.outputs.len())
+        .sum();
+    if edges > max_edges {
+        return Err(LintError(format!(
+            "graph has {} edges, more than the maximum of {}; \
+             try selecting part of it with --filter-tag or --min-owner-functions",
+            edges,
+            max_edges
+        )).into());
+    }
+    Ok(())
+}
+
+#[test]
+fn max_edges_limits_rendered_edges() {
+    let graph = crate::parse_graph(CHAIN).unwrap();
+    assert!(max_edges(&graph, 6, false).is_ok());
+    let err = max_edges(&graph, 5, false).unwrap_err();
+    assert!(err.to_string().starts_with("graph has 6 edges, more than the maximum of 5"));
+}
@@ -0,0 +1,249 @@
+//! Comparison of two versions of a dataflow graph.
+
+use crate::{Function, Graph};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// An edge in a rendered graph, from a data to the function consuming it
+/// or from a function to the data it produces.
+pub type Edge = (String, String);
+
+/// The differences between two versions of a graph. Edge changes are only
+/// included for functions present in both versions, as the edges of added
+/// or removed functions are implied by the function change.
+#[derive(Debug, Default, PartialEq)]
+pub struct GraphDiff {
+    pub added_data: Vec<String>,
+    pub removed_data: Vec<String>,
+    pub added_functions: Vec<String>,
+    pub removed_functions: Vec<String>,
+    pub added_edges: Vec<Edge>,
+    pub removed_edges: Vec<Edge>,
+    /// Functions present in both versions whose edges changed.
+    pub changed_functions: Vec<String>,
+}
+
+impl GraphDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == GraphDiff::default()
+    }
+}
+
+fn names<T, F: Fn(&T) -> &str>(items: &[T], name: F) -> BTreeSet<String> {
+    items.iter().map(|i| name(i).to_string()).collect()
+}
+
+fn edges(graph: &Graph, functions: &BTreeSet<String>) -> BTreeSet<Edge> {
+    graph
+        .functions
+        .iter()
+        .filter(|f| functions.contains(&f.name))
+        .flat_map(function_edges)
+        .collect()
+}
+
+/// Computes the changes needed to turn `old` into `new`.
+pub fn diff(old: &Graph, new: &Graph) -> GraphDiff {
+    let old_data = names(&old.data, |d| &d.name);
+    let new_data = names(&new.data, |d| &d.name);
+    let old_functions = names(&old.functions, |f| &f.name);
+    let new_functions = names(&new.functions, |f| &f.name);
+
+    let common: BTreeSet<String> = old_functions.intersection(&new_functions).cloned().collect();
+    let old_edges = edges(old, &common);
+    let new_edges = edges(new, &common);
+    let changed_functions = common
+        .iter()
+        .filter(|f| {
+            old_edges.symmetric_difference(&new_edges).any(|(from, to)| from == *f || to == *f)
+        })
+        .cloned()
+        .collect();
+
+    GraphDiff {
+        added_data: new_data.difference(&old_data).cloned().collect(),
+        removed_data: old_data.difference(&new_data).cloned().collect(),
+        added_functions: new_functions.difference(&old_functions).cloned().collect(),
+        removed_functions: old_functions.difference(&new_functions).cloned().collect(),
+        added_edges: new_edges.difference(&old_edges).cloned().collect(),
+        removed_edges: old_edges.difference(&new_edges).cloned().collect(),
+        changed_functions,
+    }
+}
+
+/// How a node or edge changed between two versions of a graph.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    /// A function whose edges changed.
+    Modified,
+}
+
+/// The changes to each node and edge drawn in an overlay of two versions
+/// of a graph. Anything not listed is unchanged.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Overlay {
+    pub nodes: HashMap<String, Change>,
+    pub edges: HashMap<Edge, Change>,
+}
+
+fn function_edges(f: &Function) -> impl Iterator<Item = Edge> + '_ {
+    f.inputs
+        .iter()
+        .map(move |i| (i.name.clone(), f.name.clone()))
+        .chain(f.outputs.iter().map(move |o| (f.name.clone(), o.name.clone())))
+}
+
+/// Returns a graph containing everything in either `old` or `new`, with
+/// the changes between them. Nodes and edges present in both versions are
+/// taken from `new`.
+pub fn overlay(old: &Graph, new: &Graph) -> (Graph, Overlay) {
+    let d = diff(old, new);
+    let mut merged = new.clone();
+    let mut overlay = Overlay::default();
+
+    merged.data.extend(old.data.iter().filter(|data| d.removed_data.contains(&data.name)).cloned());
+    merged.functions.extend(old.functions.iter().filter(|f| d.removed_functions.contains(&f.name)).cloned());
+    // Functions in both versions are drawn with their old edges too, so
+    // that the removed edges can be shown.
+    for f in merged.functions.iter_mut().filter(|f| d.changed_functions.contains(&f.name)) {
+        let name = f.name.clone();
+        for old_f in old.functions.iter().filter(|o| o.name == name) {
+            for i in &old_f.inputs {
+                if d.removed_edges.contains(&(i.name.clone(), name.clone())) {
+                    f.inputs.push(i.clone());
+                }
+            }
+            for o in &old_f.outputs {
+                if d.removed_edges.contains(&(name.clone(), o.name.clone())) {
+                    f.outputs.push(o.clone());
+                }
+            }
+        }
+    }
+
+    let changes = [
+        (&d.added_data, Change::Added),
+        (&d.removed_data, Change::Removed),
+        (&d.added_functions, Change::Added),
+        (&d.removed_functions, Change::Removed),
+        (&d.changed_functions, Change::Modified),
+    ];
+    for (names, change) in &changes {
+        overlay.nodes.extend(names.iter().map(|n| (n.clone(), *change)));
+    }
+    for (graph, names, change) in &[
+        (new, &d.added_functions, Change::Added),
+        (old, &d.removed_functions, Change::Removed)
+    ] {
+        for f in graph.functions.iter().filter(|f| names.contains(&f.name)) {
+            overlay.edges.extend(function_edges(f).map(|e| (e, *change)));
+        }
+    }
+    overlay.edges.extend(d.added_edges.iter().map(|e| (e.clone(), Change::Added)));
+    overlay.edges.extend(d.removed_edges.iter().map(|e| (e.clone(), Change::Removed)));
+
+    (merged, overlay)
+}
+
+impl fmt::Display for GraphDiff {
+    /// Writes one line per change, prefixed with + for additions
+    /// and - for removals.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for d in &self.added_data {
+            writeln!(f, "+ data {}", d)?;
+        }
+        for d in &self.removed_data {
+            writeln!(f, "- data {}", d)?;
+        }
+        for func in &self.added_functions {
+            writeln!(f, "+ function {}", func)?;
+        }
+        for func in &self.removed_functions {
+            writeln!(f, "- function {}", func)?;
+        }
+        for (from, to) in &self.added_edges {
+            writeln!(f, "+ edge {} -> {}", from, to)?;
+        }
+        for (from, to) in &self.removed_edges {
+            writeln!(f, "- edge {} -> {}", from, to)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+const OLD: &str = r#"
+data:
+  - {name: clicks, source: Browser}
+  - {name: sessions, source: Pipeline}
+  - {name: legacy, source: Pipeline}
+functions:
+  - {name: sessionise, owner: Pipeline, inputs: [clicks], outputs: [sessions, legacy]}
+"#;
+
+#[test]
+fn diff_added_function() {
+    let old = crate::parse_graph(OLD).unwrap();
+    let mut new = old.clone();
+    let mut report = new.functions[0].clone();
+    report.name = "report".into();
+    new.functions.push(report);
+
+    let d = diff(&old, &new);
+    assert_eq!(d.added_functions, vec!["report"]);
+    assert!(d.added_edges.is_empty());
+    assert!(d.changed_functions.is_empty());
+    assert_eq!(d.to_string(), "+ function report\n");
+}
+
+#[test]
+fn diff_removed_data_and_changed_edge() {
+    let old = crate::parse_graph(OLD).unwrap();
+    let new = crate::parse_graph(r#"
+data:
+  - {name: clicks, source: Browser}
+  - {name: sessions, source: Pipeline}
+functions:
+  - {name: sessionise, owner: Pipeline, inputs: [clicks], outputs: [sessions]}
+"#).unwrap();
+
+    let d = diff(&old, &new);
+    assert_eq!(d.removed_data, vec!["legacy"]);
+    assert_eq!(d.removed_edges, vec![("sessionise".to_string(), "legacy".to_string())]);
+    assert!(d.added_edges.is_empty());
+    assert_eq!(d.changed_functions, vec!["sessionise"]);
+    assert_eq!(d.to_string(), "- data legacy\n- edge sessionise -> legacy\n");
+
+    assert!(diff(&old, &old).is_empty());
+}
+
+#[test]
+fn overlay_of_both_versions() {
+    let old = crate::parse_graph(OLD).unwrap();
+    let new = crate::parse_graph(r#"
+data:
+  - {name: clicks, source: Browser}
+  - {name: sessions, source: Pipeline}
+  - {name: report, source: Pipeline}
+functions:
+  - {name: sessionise, owner: Pipeline, inputs: [clicks], outputs: [sessions]}
+  - {name: summarise, owner: Pipeline, inputs: [sessions], outputs: [report]}
+"#).unwrap();
+
+    let (merged, overlay) = overlay(&old, &new);
+    let data: Vec<&str> = merged.data.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(data, vec!["clicks", "sessions", "report", "legacy"]);
+    assert_eq!(merged.functions[0].outputs.len(), 2);
+
+    assert_eq!(overlay.nodes["legacy"], Change::Removed);
+    assert_eq!(overlay.nodes["report"], Change::Added);
+    assert_eq!(overlay.nodes["summarise"], Change::Added);
+    assert_eq!(overlay.nodes["sessionise"], Change::Modified);
+    assert!(!overlay.nodes.contains_key("clicks"));
+    let edge = |from: &str, to: &str| overlay.edges.get(&(from.to_string(), to.to_string())).cloned();
+    assert_eq!(edge("sessionise", "legacy"), Some(Change::Removed));
+    assert_eq!(edge("summarise", "report"), Some(Change::Added));
+    assert_eq!(edge("clicks", "sessionise"), None);
+}
@@ -0,0 +1,367 @@
+//! Transformations selecting and simplifying the parts of a graph to
+//! render.
+
+use crate::{Flow, Function, Graph};
+use std::collections::{HashMap, HashSet};
+
+/// Returns the functions of `graph` with at least one of `tags`, along
+/// with the data they consume or produce.
+pub fn by_tags(graph: &Graph, tags: &[String]) -> Graph {
+    select(graph, |f| f.tags.iter().any(|t| tags.contains(t)), |_| false)
+}
+
+/// Returns the functions and data of `graph` named in `names`, along with
+/// the data consumed or produced by those functions.
+pub fn by_names(graph: &Graph, names: &HashSet<String>) -> Graph {
+    select(graph, |f| names.contains(&f.name), |d| names.contains(d))
+}
+
+/// Returns the functions of `graph` with the given owner, along with the
+/// data they consume or produce.
+pub fn by_owner(graph: &Graph, owner: &str) -> Graph {
+    select(graph, |f| f.owner == owner, |_| false)
+}
+
+/// Removes the data named in `names`, and the flows to or from them, from
+/// `graph`, e.g. to hide a config read by every function. Functions left
+/// with no inputs or outputs are also removed if `prune` is set.
+pub fn without_data(graph: &Graph, names: &[String], prune: bool) -> Graph {
+    let excluded = |name: &str| names.iter().any(|n| n == name);
+    let functions = graph
+        .functions
+        .iter()
+        .map(|f| {
+            let mut f = f.clone();
+            f.inputs.retain(|i| !excluded(&i.name));
+            f.outputs.retain(|o| !excluded(&o.name));
+            f
+        })
+        .filter(|f| !prune || !f.inputs.is_empty() || !f.outputs.is_empty())
+        .collect();
+    let data = graph.data.iter().filter(|d| !excluded(&d.name)).cloned().collect();
+
+    Graph { data, functions, legend: graph.legend.clone(), notes: graph.notes.clone() }
+}
+
+/// Removes the repeated inputs and outputs of each function in `graph`,
+/// i.e. those of the same data to the same port, keeping the first of each.
+pub fn without_duplicate_flows(graph: &Graph) -> Graph {
+    let dedupe = |flows: &[Flow]| -> Vec<Flow> {
+        let mut unique: Vec<Flow> = Vec::new();
+        for flow in flows {
+            if !unique.iter().any(|u| (&u.name, &u.port) == (&flow.name, &flow.port)) {
+                unique.push(flow.clone());
+            }
+        }
+        unique
+    };
+    let functions = graph
+        .functions
+        .iter()
+        .map(|f| Function { inputs: dedupe(&f.inputs), outputs: dedupe(&f.outputs), ..f.clone() })
+        .collect();
+
+    Graph { data: graph.data.clone(), functions, legend: graph.legend.clone(), notes: graph.notes.clone() }
+}
+
+/// Parses a list of node names, one per line. Blank lines and lines
+/// starting with `#` are ignored.
+pub fn parse_allowlist(text: &str) -> HashSet<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the functions of `graph` matching `keep_function`, and the data
+/// which are either adjacent to one of these or match `keep_data`.
+fn select(
+    graph: &Graph,
+    keep_function: impl Fn(&Function) -> bool,
+    keep_data: impl Fn(&str) -> bool
+) -> Graph {
+    let functions: Vec<_> = graph
+        .functions
+        .iter()
+        .filter(|f| keep_function(f))
+        .cloned()
+        .collect();
+
+    let adjacent: HashSet<&str> = functions
+        .iter()
+        .flat_map(|f| f.inputs.iter().chain(&f.outputs))
+        .map(|flow| flow.name.as_str())
+        .collect();
+
+    let data = graph
+        .data
+        .iter()
+        .filter(|d| adjacent.contains(d.name.as_str()) || keep_data(&d.name))
+        .cloned()
+        .collect();
+
+    Graph { data, functions, legend: graph.legend.clone(), notes: graph.notes.clone() }
+}
+
+/// Removes the functions whose owner has fewer than `min` functions, to
+/// declutter diagrams with many minor owners. Returns the filtered graph
+/// and the number of functions removed.
+pub fn by_min_owner_functions(graph: &Graph, min: usize) -> (Graph, usize) {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for f in &graph.functions {
+        *counts.entry(&f.owner).or_default() += 1;
+    }
+
+    let functions: Vec<_> = graph
+        .functions
+        .iter()
+        .filter(|f| counts[f.owner.as_str()] >= min)
+        .cloned()
+        .collect();
+    let hidden = graph.functions.len() - functions.len();
+
+    (Graph { data: graph.data.clone(), functions, legend: graph.legend.clone(), notes: graph.notes.clone() }, hidden)
+}
+
+/// Replaces each maximal chain of functions, where every function has a
+/// single input and output and each output is read only by the next
+/// function, with a single function named after the whole chain, e.g.
+/// "f1→f2→f3". The data between the functions are removed. Functions are
+/// only chained if they have the same owner and are all enabled or all
+/// disabled, so each collapsed node can be coloured as before. Other fields
+/// of a collapsed function are taken from the first in its chain.
+pub fn collapse_chains(graph: &Graph) -> Graph {
+    let mut producers: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut consumers: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, f) in graph.functions.iter().enumerate() {
+        for flow in &f.inputs {
+            consumers.entry(&flow.name).or_default().push(i);
+        }
+        for flow in &f.outputs {
+            producers.entry(&flow.name).or_default().push(i);
+        }
+    }
+
+    let linear = |f: &Function| f.inputs.len() == 1 && f.outputs.len() == 1;
+    let mut next: HashMap<usize, usize> = HashMap::new();
+    for (i, f) in graph.functions.iter().enumerate() {
+        if !linear(f) {
+            continue;
+        }
+        let output = f.outputs[0].name.as_str();
+        if let (Some([_]), Some(&[j])) = (
+            producers.get(output).map(Vec::as_slice),
+            consumers.get(output).map(Vec::as_slice)
+        ) {
+            let g = &graph.functions[j];
+            if j != i && linear(g) && g.owner == f.owner && g.is_enabled() == f.is_enabled() {
+                next.insert(i, j);
+            }
+        }
+    }
+
+    // Chains start at a function with a successor but no predecessor, so
+    // functions in a cycle are never collapsed.
+    let has_previous: HashSet<usize> = next.values().cloned().collect();
+    let mut chains: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut collapsed = HashSet::new();
+    for &start in next.keys().filter(|i| !has_previous.contains(i)) {
+        let mut chain = vec![start];
+        while let Some(&j) = next.get(chain.last().unwrap()) {
+            chain.push(j);
+        }
+        collapsed.extend(chain.iter().skip(1).cloned());
+        chains.insert(start, chain);
+    }
+
+    let mut removed_data = HashSet::new();
+    let mut functions = Vec::new();
+    for (i, f) in graph.functions.iter().enumerate() {
+        if let Some(chain) = chains.get(&i) {
+            let members: Vec<&Function> = chain.iter().map(|&j| &graph.functions[j]).collect();
+            let last = members[members.len() - 1];
+            let mut function = f.clone();
+            function.name = members.iter().map(|g| g.name.as_str()).collect::<Vec<_>>().join("→");
+            function.outputs = last.outputs.clone();
+            removed_data.extend(members[..members.len() - 1].iter().map(|g| g.outputs[0].name.as_str()));
+            functions.push(function);
+        } else if !collapsed.contains(&i) {
+            functions.push(f.clone());
+        }
+    }
+
+    let data = graph
+        .data
+        .iter()
+        .filter(|d| !removed_data.contains(d.name.as_str()))
+        .cloned()
+        .collect();
+
+    Graph { data, functions, legend: graph.legend.clone(), notes: graph.notes.clone() }
+}
+
+#[test]
+fn linear_chain_collapsed() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: d1, source: s}
+  - {name: d2, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: f1, owner: o, inputs: [a], outputs: [d1]}
+  - {name: f2, owner: o, inputs: [d1], outputs: [d2]}
+  - {name: f3, owner: o, inputs: [d2], outputs: [b]}
+"#).unwrap();
+
+    let collapsed = collapse_chains(&graph);
+    let data: Vec<&str> = collapsed.data.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(data, vec!["a", "b"]);
+    assert_eq!(collapsed.functions.len(), 1);
+    let f = &collapsed.functions[0];
+    assert_eq!(f.name, "f1→f2→f3");
+    assert_eq!(f.inputs[0].name, "a");
+    assert_eq!(f.outputs[0].name, "b");
+}
+
+#[test]
+fn chains_not_collapsed_across_branches() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: d1, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: f1, owner: o, inputs: [a], outputs: [d1]}
+  - {name: f2, owner: o, inputs: [d1], outputs: [b]}
+  - {name: f3, owner: o, inputs: [d1], outputs: [c]}
+"#).unwrap();
+
+    assert_eq!(collapse_chains(&graph), graph);
+}
+
+#[test]
+fn filter_by_min_owner_functions() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f1, owner: Single, inputs: [a], outputs: []}
+  - {name: f2, owner: Pair, inputs: [a], outputs: []}
+  - {name: f3, owner: Pair, inputs: [a], outputs: []}
+  - {name: f4, owner: Triple, inputs: [a], outputs: []}
+  - {name: f5, owner: Triple, inputs: [a], outputs: []}
+  - {name: f6, owner: Triple, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let (filtered, hidden) = by_min_owner_functions(&graph, 2);
+    let functions: Vec<&str> = filtered.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(functions, vec!["f2", "f3", "f4", "f5", "f6"]);
+    assert_eq!(hidden, 1);
+    assert_eq!(filtered.data, graph.data);
+}
+
+#[test]
+fn filter_by_tag() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: signups, source: Web}
+  - {name: users, source: Accounts}
+  - {name: metrics, source: Monitoring}
+  - {name: emails, source: Marketing}
+functions:
+  - name: register
+    owner: Accounts
+    tags: [pii, realtime]
+    inputs: [signups]
+    outputs: [users]
+  - name: monitor
+    owner: Monitoring
+    tags: [realtime]
+    inputs: [signups]
+    outputs: [metrics]
+  - name: newsletter
+    owner: Marketing
+    tags:
+    inputs: [users]
+    outputs: [emails]
+"#).unwrap();
+
+    let filtered = by_tags(&graph, &["realtime".to_string()]);
+    let functions: Vec<&str> = filtered.functions.iter().map(|f| f.name.as_str()).collect();
+    let data: Vec<&str> = filtered.data.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(functions, vec!["register", "monitor"]);
+    assert_eq!(data, vec!["signups", "users", "metrics"]);
+
+    let filtered = by_tags(&graph, &["pii".to_string(), "missing".to_string()]);
+    assert_eq!(filtered.functions.len(), 1);
+}
+
+#[test]
+fn excluded_data_removed_with_edges() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: config, source: Ops}
+  - {name: orders, source: Shop}
+  - {name: invoices, source: Billing}
+functions:
+  - {name: bill, owner: Billing, inputs: [config, orders], outputs: [invoices]}
+  - {name: audit, owner: Ops, inputs: [config], outputs: []}
+"#).unwrap();
+
+    let filtered = without_data(&graph, &["config".to_string()], false);
+    let data: Vec<&str> = filtered.data.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(data, vec!["orders", "invoices"]);
+    assert_eq!(filtered.functions.len(), 2);
+    assert!(filtered.functions.iter().all(|f| f.inputs.iter().all(|i| i.name != "config")));
+    assert_eq!(filtered.functions[0].inputs.len(), 1);
+
+    let pruned = without_data(&graph, &["config".to_string()], true);
+    let functions: Vec<&str> = pruned.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(functions, vec!["bill"]);
+}
+
+#[test]
+fn duplicate_flows_removed() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a, b, a], outputs: [b, b]}
+  - {name: g, owner: o, inputs: [a, {name: a, note: x}, {name: a, port: p}], outputs: [{name: b, weight: 2}, b]}
+"#).unwrap();
+
+    let deduped = without_duplicate_flows(&graph);
+    let inputs: Vec<&str> = deduped.functions[0].inputs.iter().map(|i| i.name.as_str()).collect();
+    assert_eq!(inputs, vec!["a", "b"]);
+    assert_eq!(deduped.functions[0].outputs.len(), 1);
+    let g = &deduped.functions[1];
+    let inputs: Vec<(Option<&str>, Option<&str>)> =
+        g.inputs.iter().map(|i| (i.note.as_deref(), i.port.as_deref())).collect();
+    assert_eq!(inputs, vec![(None, None), (None, Some("p"))]);
+    let weights: Vec<Option<u32>> = g.outputs.iter().map(|o| o.weight).collect();
+    assert_eq!(weights, vec![Some(2)]);
+}
+
+#[test]
+fn filter_by_allowlist() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: raw, source: s}
+  - {name: clean, source: s}
+  - {name: report, source: s}
+functions:
+  - {name: cleanse, owner: o, inputs: [raw], outputs: [clean]}
+  - {name: summarise, owner: o, inputs: [clean], outputs: [report]}
+"#).unwrap();
+
+    let names = parse_allowlist("# focus on cleaning\n\n  cleanse  \n");
+    let filtered = by_names(&graph, &names);
+    let functions: Vec<&str> = filtered.functions.iter().map(|f| f.name.as_str()).collect();
+    let data: Vec<&str> = filtered.data.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(functions, vec!["cleanse"]);
+    assert_eq!(data, vec!["raw", "clean"]);
+}
@@ -0,0 +1,110 @@
+//! Canonical formatting of dataflow specifications, so that equivalent
+//! specs are written identically and diffs between them are minimal.
+
+use crate::Graph;
+use serde_yaml::{Mapping, Value};
+
+/// Serializes `graph` as YAML with data and functions sorted by name,
+/// inputs and outputs sorted, and mapping keys in alphabetical order.
+pub fn canonical_yaml(graph: &Graph) -> Result<String, serde_yaml::Error> {
+    let mut graph = graph.clone();
+    graph.data.sort_by(|a, b| a.name.cmp(&b.name));
+    graph.functions.sort_by(|a, b| a.name.cmp(&b.name));
+    for f in &mut graph.functions {
+        f.inputs.sort();
+        f.outputs.sort();
+    }
+
+    let value = sort_keys(serde_yaml::to_value(&graph)?);
+    let mut yaml = serde_yaml::to_string(&value)?;
+    if !yaml.ends_with('\n') {
+        yaml.push('\n');
+    }
+    Ok(yaml)
+}
+
+/// Hashes the canonical form of `graph`, so that specs differing only in
+/// formatting or order have the same hash. This uses 64-bit FNV-1a rather
+/// than `DefaultHasher`, whose output may change between Rust releases.
+pub fn canonical_hash(graph: &Graph) -> Result<u64, serde_yaml::Error> {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let hash = canonical_yaml(graph)?
+        .bytes()
+        .fold(OFFSET_BASIS, |hash, b| (hash ^ u64::from(b)).wrapping_mul(PRIME));
+    Ok(hash)
+}
+
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Mapping(mapping) => {
+            let mut entries: Vec<(Value, Value)> = mapping.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.as_str().cmp(&b.as_str()));
+            let mut sorted = Mapping::new();
+            for (k, v) in entries {
+                sorted.insert(k, sort_keys(v));
+            }
+            Value::Mapping(sorted)
+        }
+        Value::Sequence(values) => Value::Sequence(values.into_iter().map(sort_keys).collect()),
+        other => other,
+    }
+}
+
+#[test]
+fn formatting_is_stable() {
+    let messy = crate::parse_graph(r#"
+functions:
+  - outputs:   [Position]
+    name: Update
+    inputs: [  MouseEvents, KeyboardEvents ]
+    owner: Game
+  - owner: Audio
+    name: Beep
+    inputs:
+      - KeyboardEvents
+    outputs: []
+data:
+  - source: Player
+    name: MouseEvents
+  - name: KeyboardEvents
+    source: Player
+    description: Keys pressed this frame
+  - {source: GameLoop, name: Position}
+"#).unwrap();
+
+    let once = canonical_yaml(&messy).unwrap();
+    let reparsed = crate::parse_graph(&once).unwrap();
+    let twice = canonical_yaml(&reparsed).unwrap();
+
+    assert_eq!(once, twice);
+    assert!(once.find("name: Beep").unwrap() < once.find("name: Update").unwrap());
+    assert!(once.find("KeyboardEvents").unwrap() < once.find("MouseEvents").unwrap());
+    assert!(once.find("- KeyboardEvents\n      - MouseEvents").is_some());
+}
+
+#[test]
+fn hash_ignores_formatting() {
+    let spec = r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a, b], outputs: []}
+"#;
+    let reordered = r#"
+functions:
+  - outputs: []
+    inputs: [b, a]
+    owner: o
+    name: f
+data:
+  - {source: s, name: b}
+  - {source: s, name: a}
+"#;
+    let hash = |spec| canonical_hash(&crate::parse_graph(spec).unwrap()).unwrap();
+
+    assert_eq!(hash(spec), hash(reordered));
+    assert_ne!(hash(spec), hash(&spec.replace("inputs: [a, b]", "inputs: [a]")));
+}
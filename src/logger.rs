@@ -0,0 +1,52 @@
+//! A minimal logger writing diagnostics to stderr, with the level chosen
+//! by the number of -v flags given.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error => eprintln!("error: {}", record.args()),
+            Level::Warn => eprintln!("warning: {}", record.args()),
+            _ => eprintln!("{}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// The most verbose level logged for the given number of -v flags. Only
+/// warnings are logged by default.
+fn level_filter(verbosity: u64) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Installs the logger. Must be called at most once.
+pub fn init(verbosity: u64) {
+    log::set_logger(&LOGGER).expect("logger already installed");
+    log::set_max_level(level_filter(verbosity));
+}
+
+#[test]
+fn verbosity_levels() {
+    assert_eq!(level_filter(0), LevelFilter::Warn);
+    assert_eq!(level_filter(1), LevelFilter::Info);
+    assert_eq!(level_filter(2), LevelFilter::Debug);
+    assert_eq!(level_filter(5), LevelFilter::Trace);
+}
@@ -1,291 +1,5018 @@
 use failure::ResultExt;
 use exitfailure::ExitFailure;
+use log::{debug, info, warn};
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
 use structopt::StructOpt;
 
+use crate::cache::SpecCache;
+use crate::profile::Profile;
+
+mod analysis;
+mod cache;
+mod checks;
+mod diff;
+mod filter;
+mod format;
+mod logger;
+mod profile;
+mod tree;
+
 #[derive(StructOpt)]
 struct Cli {
-    /// Path to file containing dataflow specification.
+    /// Path to file containing dataflow specification, or - to read from
+    /// stdin, or an http(s) URL if built with the remote feature. If built
+    /// with the glob feature this can also be a pattern such as
+    /// 'specs/*.yaml', to merge the specs in all matching files. Required
+    /// unless running a subcommand.
     #[structopt(parse(from_os_str), long = "input", short = "i")]
-    input: std::path::PathBuf,
+    input: Option<std::path::PathBuf>,
+    /// Minimise the size of the generated DOT, e.g. for committing it to
+    /// version control. The rendered graph is unchanged.
+    #[structopt(long = "compact")]
+    compact: bool,
+    /// Treat problems found when checking the spec as errors rather
+    /// than warnings.
+    #[structopt(long = "strict")]
+    strict: bool,
+    /// Warn about and ignore inputs or outputs listed more than once by a
+    /// function, rather than rejecting the spec.
+    #[structopt(long = "dedupe-flows")]
+    dedupe_flows: bool,
+    /// Also reject data whose source is empty or only whitespace. Names
+    /// and owners are always required.
+    #[structopt(long = "strict-schema")]
+    strict_schema: bool,
+    /// Graphviz layout engine to request in the generated DOT, one of
+    /// dot, neato, fdp, sfdp, circo, twopi, osage or patchwork.
+    #[structopt(long = "layout")]
+    layout: Option<Layout>,
+    /// Seed for the random initial positions of the neato, fdp and sfdp
+    /// layouts, so that the graph is laid out the same way each time.
+    /// Ignored by other layouts.
+    #[structopt(long = "layout-seed")]
+    layout_seed: Option<u64>,
+    /// How to draw edges, one of ortho for horizontal and vertical lines,
+    /// polyline, curved or line. Graphviz draws splines by default.
+    #[structopt(long = "splines")]
+    splines: Option<Splines>,
+    /// Render disabled functions in grey rather than omitting them.
+    #[structopt(long = "show-disabled")]
+    show_disabled: bool,
+    /// Arrowhead for edges with a given kind of flow, as kind=arrowhead,
+    /// e.g. --arrowhead write=box. Defaults are read=normal, write=diamond
+    /// and event=vee.
+    #[structopt(
+        long = "arrowhead",
+        parse(try_from_str = "parse_key_value"),
+        raw(number_of_values = "1")
+    )]
+    arrowheads: Vec<(String, String)>,
+    /// Fail if any chain of functions, each consuming an output of the
+    /// one before, contains more than this many functions.
+    #[structopt(long = "max-depth")]
+    max_depth: Option<usize>,
+    /// Fail if the rendered graph would have more than this many edges,
+    /// rather than leaving Graphviz to struggle with it.
+    #[structopt(long = "max-edges")]
+    max_edges: Option<usize>,
+    /// Fail if any data is neither consumed nor produced by a function,
+    /// regardless of --strict.
+    #[structopt(long = "assert-no-orphans")]
+    assert_no_orphans: bool,
+    /// Fail if any data is produced by more than one function, regardless
+    /// of --strict.
+    #[structopt(long = "assert-single-producer")]
+    assert_single_producer: bool,
+    /// Fail if any data isn't produced by a function, e.g. an external
+    /// input, regardless of --strict.
+    #[structopt(long = "assert-has-producer")]
+    assert_has_producer: bool,
+    /// Fail if the graph falls into several parts not joined by any flow,
+    /// ignoring disabled functions, regardless of --strict.
+    #[structopt(long = "assert-connected")]
+    assert_connected: bool,
+    /// Fail if two functions, or two data, have the same name, regardless
+    /// of --strict. Otherwise functions of different owners with the same
+    /// name are drawn as separate nodes, with ids prefixed by their owners.
+    #[structopt(long = "require-unique-names")]
+    require_unique_names: bool,
+    /// Fail if any function's owner isn't listed in this file, which has
+    /// one owner per line. Blank lines and lines starting with # are
+    /// ignored.
+    #[structopt(parse(from_os_str), long = "allowed-owners")]
+    allowed_owners: Option<std::path::PathBuf>,
+    /// Print the number of distinct paths between two nodes, given as
+    /// --count-paths <from> <to>, instead of rendering the graph.
+    #[structopt(long = "count-paths", raw(number_of_values = "2", value_names = r#"&["from", "to"]"#))]
+    count_paths: Vec<String>,
+    /// Print the number of functions and data, and the number of functions
+    /// on the longest chain through the graph, instead of rendering it.
+    #[structopt(long = "stats")]
+    stats: bool,
+    /// Print the time spent in each phase of the run to stderr.
+    #[structopt(long = "profile")]
+    profile: bool,
+    /// Log progress to stderr, with -v for an outline of each run and -vv
+    /// for more detail. Only warnings are logged by default.
+    #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
+    verbose: u64,
+    /// Warn about functions which read one of their own outputs.
+    #[structopt(long = "warn-self-dependencies")]
+    warn_self_dependencies: bool,
+    /// Warn about owners and sources which look like placeholders, i.e.
+    /// one of those given by --placeholders or an unexpanded variable such
+    /// as ${TEAM}.
+    #[structopt(long = "warn-placeholders")]
+    warn_placeholders: bool,
+    /// Comma-separated list of the placeholders for --warn-placeholders
+    /// to look for, ignoring case.
+    #[structopt(long = "placeholders", default_value = "TODO,FIXME,TBD,???", raw(use_delimiter = "true"))]
+    placeholders: Vec<String>,
+    /// Warn about data produced by a function in one document or file of a
+    /// merged spec but only read, as if an external input, in another.
+    /// Specs read with this flag aren't cached.
+    #[structopt(long = "warn-mixed-roles")]
+    warn_mixed_roles: bool,
+    /// Warn about pairs of names within this many single character edits
+    /// of each other, which are likely to be typos.
+    #[structopt(long = "warn-similar-names")]
+    warn_similar_names: Option<usize>,
+    /// Warn about data which are produced but never consumed, unless
+    /// they're marked with role: sink.
+    #[structopt(long = "warn-dead-outputs")]
+    warn_dead_outputs: bool,
+    /// Warn about data which several functions write, and others read or
+    /// also write, as these may need coordination between the writers.
+    #[structopt(long = "warn-shared-state")]
+    warn_shared_state: bool,
+    /// Render a graph of owners rather than functions and data, with an
+    /// edge from one owner to another if the second consumes data produced
+    /// by the first.
+    #[structopt(long = "owner-graph")]
+    owner_graph: bool,
+    /// Render only the data, with an edge from one data to another if
+    /// some function consumes the first and produces the second.
+    #[structopt(long = "data-only", raw(conflicts_with = r#""owner_graph""#))]
+    data_only: bool,
+    /// Write a separate DOT file for each owner to --output-dir, containing
+    /// the owner's functions and the data they consume or produce.
+    #[structopt(long = "split-by-owner", raw(requires = r#""output_dir""#))]
+    split_by_owner: bool,
+    /// Number of owners to render at once with --split-by-owner. Ignored
+    /// otherwise.
+    #[structopt(long = "jobs", short = "j", default_value = "1")]
+    jobs: usize,
+    /// Directory to write files to when splitting the output.
+    #[structopt(parse(from_os_str), long = "output-dir")]
+    output_dir: Option<std::path::PathBuf>,
+    /// Name of the generated digraph, with any characters not allowed in
+    /// a DOT identifier replaced by underscores.
+    #[structopt(long = "graph-name", default_value = "G")]
+    graph_name: String,
+    /// Title of the legend.
+    #[structopt(long = "legend-title", default_value = "Legend")]
+    legend_title: String,
+    /// Place the legend at the top, bottom or right of the graph, rather
+    /// than wherever Graphviz finds room for it.
+    #[structopt(long = "legend-position")]
+    legend_position: Option<LegendPosition>,
+    /// Label each legend entry with the initials of its owner, e.g. "DP"
+    /// for "Data Platform" or "platform/ingest", to keep the legend narrow.
+    /// The full name is shown when hovering over the entry in SVG.
+    #[structopt(long = "legend-initials")]
+    legend_initials: bool,
+    /// Comma-separated list of colours to assign to owners, used in
+    /// place of the default colour scheme, e.g. "#1f77b4,#ff7f0e,red".
+    #[structopt(long = "palette")]
+    palette: Option<Palette>,
+    /// Colour owners from a palette distinguishable with common forms of
+    /// colour blindness. Ignored if --palette is given.
+    #[structopt(long = "colorblind-safe")]
+    colorblind_safe: bool,
+    /// Show the source of each data beneath its name.
+    #[structopt(long = "show-data-source")]
+    show_data_source: bool,
+    /// Emit only the node, edge and cluster statements, without the
+    /// enclosing digraph or the legend, for including in a larger DOT file.
+    #[structopt(long = "fragment")]
+    fragment: bool,
+    /// Give nodes and edges SVG classes naming their owner, e.g.
+    /// class="function owner_billing", and legend entries matching ids, so
+    /// that a script on the page showing the SVG can highlight an owner's
+    /// footprint when its legend entry is clicked.
+    #[structopt(long = "interactive")]
+    interactive: bool,
+    /// Colour each edge by the source of its data, using the same palette
+    /// as owners. Edges of data with no source stay black.
+    #[structopt(long = "color-edges-by-source")]
+    color_edges_by_source: bool,
+    /// Print the reasons for the colour, shape and style of each node to
+    /// stderr, e.g. to find out why a function was drawn in grey. Only
+    /// applies to DOT output.
+    #[structopt(long = "explain")]
+    explain: bool,
+    /// Draw data entering the system from outside, i.e. consumed but not
+    /// produced by any function, with a dashed border.
+    #[structopt(long = "include-external")]
+    include_external: bool,
+    /// Output format, either dot for Graphviz DOT or tree for an
+    /// indented text summary of the pipeline.
+    #[structopt(long = "format", default_value = "dot")]
+    format: OutputFormat,
+    /// Only render functions with this tag, and the data they consume or
+    /// produce. May be given more than once to include several tags.
+    #[structopt(long = "filter-tag", raw(number_of_values = "1"))]
+    filter_tags: Vec<String>,
+    /// Hide this data, and its edges, e.g. a config read by every function.
+    /// May be given more than once to hide several data.
+    #[structopt(long = "exclude-data", raw(number_of_values = "1"))]
+    exclude_data: Vec<String>,
+    /// Also hide functions left with no inputs or outputs by --exclude-data.
+    #[structopt(long = "prune-isolated", raw(requires = r#""exclude_data""#))]
+    prune_isolated: bool,
+    /// Path to a file listing the names of the functions and data to
+    /// render, one per line. Data consumed or produced by the listed
+    /// functions are also rendered. Blank lines and lines starting with #
+    /// are ignored.
+    #[structopt(parse(from_os_str), long = "only-file")]
+    only_file: Option<std::path::PathBuf>,
+    /// Hide the functions of owners with fewer than this many functions.
+    #[structopt(long = "min-owner-functions")]
+    min_owner_functions: Option<usize>,
+    /// Lay out each owner's functions in their own horizontal lane.
+    #[structopt(long = "swimlanes")]
+    swimlanes: bool,
+    /// Group function nodes by owner in the generated DOT, with a comment
+    /// naming the owner before each group.
+    #[structopt(long = "annotate-owners")]
+    annotate_owners: bool,
+    /// Show the number of inputs and outputs of each function after its
+    /// name, e.g. "enrich (2→1)".
+    #[structopt(long = "show-degree")]
+    show_degree: bool,
+    /// Add a table below the graph listing the source and description of
+    /// each data.
+    #[structopt(long = "with-catalog")]
+    with_catalog: bool,
+    /// Add an overview of a large graph in its own cluster, with a node
+    /// for each owner and an edge wherever one owner's functions read data
+    /// produced by another's, as in --owner-graph.
+    #[structopt(long = "overview")]
+    overview: bool,
+    /// Scale the font size of each node with its number of inputs and
+    /// outputs, so that busy nodes stand out.
+    #[structopt(long = "scale-by-degree")]
+    scale_by_degree: bool,
+    /// Fill each node by its metric, from green for the lowest value in the
+    /// graph to red for the highest, instead of by owner or freshness. Nodes
+    /// without a metric, or whose metric is NaN or infinite, are filled white.
+    #[structopt(long = "heatmap")]
+    heatmap: bool,
+    /// Draw each function as a record with a port for each of its inputs
+    /// along the top and each of its outputs along the bottom, to which
+    /// the edges attach. Ignores the ports given in the spec.
+    #[structopt(long = "ports")]
+    ports: bool,
+    /// Prefix the name of each function with its position in an order
+    /// where every function comes after those it reads from, e.g.
+    /// "[3] enrich". Ties are broken by name. Fails if the functions form
+    /// a cycle.
+    #[structopt(long = "number-functions")]
+    number_functions: bool,
+    /// Emit all node declarations before any edges, with the edges sorted,
+    /// so that the DOT is easier to read and diff. The rendered graph is
+    /// unchanged.
+    #[structopt(long = "group-edges")]
+    group_edges: bool,
+    /// Draw functions with the same owner, inputs and outputs as a single
+    /// node, labelled with all of their names.
+    #[structopt(long = "merge-duplicates")]
+    merge_duplicates: bool,
+    /// Draw each chain of functions with a single input and output, each
+    /// reading the output of the one before, as a single node labelled
+    /// with the chain, e.g. "f1→f2→f3".
+    #[structopt(long = "collapse-chains")]
+    collapse_chains: bool,
+    /// Draw each owner's functions in a cluster labelled with the owner.
+    /// Owners are paths separated by /, e.g. platform/ingest, and the
+    /// clusters of owners sharing a prefix are nested in a cluster for it.
+    #[structopt(long = "cluster-owners")]
+    cluster_owners: bool,
+    /// Draw the functions of this owner with a bold border, and everything
+    /// not connected to them in grey. Unlike filtering, the rest of the
+    /// graph remains visible for context.
+    #[structopt(long = "highlight-owner")]
+    highlight_owner: Option<String>,
+    /// Use short ids such as n1 and n2 for nodes in the generated DOT,
+    /// with the full names as labels, to shrink very large diagrams.
+    #[structopt(long = "minify-ids")]
+    minify_ids: bool,
+    /// Write the name for each id to this file, one per line separated
+    /// by a tab, when minifying ids.
+    #[structopt(parse(from_os_str), long = "id-map", raw(requires = r#""minify_ids""#))]
+    id_map: Option<std::path::PathBuf>,
+    /// Draw each independent pipeline, i.e. set of nodes not connected to
+    /// the rest of the graph, in its own titled cluster.
+    #[structopt(long = "label-components")]
+    label_components: bool,
+    /// Shape of data nodes, e.g. box, cylinder or note.
+    #[structopt(long = "data-shape", default_value = "box")]
+    data_shape: Shape,
+    /// Shape of function nodes, e.g. ellipse, box or diamond.
+    #[structopt(long = "function-shape", default_value = "ellipse")]
+    function_shape: Shape,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-/// cargo run -- -i src/sample.yaml | dot -Tsvg >> sample.svg
-fn main() -> Result<(), ExitFailure> {
-    let args = Cli::from_args();
-
-    let content = std::fs::read_to_string(&args.input)
-        .with_context(
-            |_| format!("could not read file '{:?}'", &args.input)
-        )?;
-
-    let graph: Graph = serde_yaml::from_str(&content).expect("parse failure");
-    render(&mut std::io::stdout(), &graph)?;
-    Ok(())
+#[derive(StructOpt)]
+enum Command {
+    /// Rewrite a dataflow specification in canonical form.
+    #[structopt(name = "fmt")]
+    Fmt {
+        /// Path to file containing dataflow specification.
+        #[structopt(parse(from_os_str), long = "input", short = "i")]
+        input: std::path::PathBuf,
+        /// Write the formatted spec to stdout rather than back to the input file.
+        #[structopt(long = "stdout")]
+        stdout: bool,
+    },
+    /// Print a hash of a dataflow specification which only changes when
+    /// the graph does, and not when the spec is reformatted or reordered.
+    #[structopt(name = "hash")]
+    Hash {
+        /// Path to file containing dataflow specification.
+        #[structopt(parse(from_os_str), long = "input", short = "i")]
+        input: std::path::PathBuf,
+    },
+    /// Report the data, functions and edges added or removed between
+    /// two versions of a dataflow specification.
+    #[structopt(name = "diff")]
+    Diff {
+        /// Path to the old version of the specification.
+        #[structopt(parse(from_os_str), short = "a")]
+        old: std::path::PathBuf,
+        /// Path to the new version of the specification.
+        #[structopt(parse(from_os_str), short = "b")]
+        new: std::path::PathBuf,
+        /// Render both versions as a single DOT graph instead, with added
+        /// nodes and edges outlined in green, removed ones dashed in red and
+        /// functions whose edges changed outlined in orange.
+        #[structopt(long = "overlay")]
+        overlay: bool,
+    },
+    /// Print a JSON Schema describing the specification format, for
+    /// validation and autocompletion in editors.
+    #[structopt(name = "schema")]
+    Schema,
+    /// Render each of several dataflow specifications to a DOT file of the
+    /// same name in an output directory. Fails before rendering anything if
+    /// two specifications would be written to the same file.
+    #[structopt(name = "batch")]
+    Batch {
+        /// Paths to the specifications to render.
+        #[structopt(parse(from_os_str), required = true)]
+        inputs: Vec<std::path::PathBuf>,
+        /// Directory to write the DOT files to.
+        #[structopt(parse(from_os_str), long = "output-dir", short = "o")]
+        output_dir: std::path::PathBuf,
+        /// Render the remaining specifications after one fails, then print
+        /// how many succeeded and why each failure failed. Fails if any
+        /// specification failed.
+        #[structopt(long = "continue-on-error")]
+        continue_on_error: bool,
+        /// Number of specifications to render at once. Failures are still
+        /// reported in the order the specifications were given, but with
+        /// more than one job, some specifications after a failure may be
+        /// rendered before the batch stops.
+        #[structopt(long = "jobs", short = "j", default_value = "1")]
+        jobs: usize,
+    },
 }
 
-struct DotBuilder {
-    lines: Vec<String>
+/// The format in which to render a graph.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Dot,
+    Tree
 }
 
-impl DotBuilder {
-    fn new() -> DotBuilder {
-        DotBuilder { lines: Vec::new() }
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "dot" => Ok(OutputFormat::Dot),
+            "tree" => Ok(OutputFormat::Tree),
+            _ => Err(format!("unknown format '{}', expected dot or tree", s))
+        }
     }
+}
 
-    fn add<S: Into<String>>(&mut self, line: S) {
-        self.lines.push(line.into());
+impl OutputFormat {
+    /// The renderer for this format, prepared to render `graph`. Options
+    /// which don't apply to the format are ignored.
+    fn renderer(self, opts: RenderOptions, graph: &Graph) -> Box<dyn Renderer> {
+        match self {
+            OutputFormat::Dot => Box::new(DotRenderer {
+                colours: owner_colours(graph, &opts.palette),
+                opts
+            }),
+            OutputFormat::Tree => Box::new(tree::TreeRenderer),
+        }
     }
+}
 
-    fn add_node(&mut self, node: &Node) {
-        self.lines.push(node.to_string());
+/// Writes graphs in some output format.
+trait Renderer {
+    fn render(&self, w: &mut dyn std::io::Write, graph: &Graph) -> std::io::Result<()>;
+}
+
+/// Renders graphs as Graphviz DOT.
+struct DotRenderer {
+    opts: RenderOptions,
+    /// Colours for the owners of the graph being rendered.
+    colours: BTreeMap<String, String>,
+}
+
+impl Renderer for DotRenderer {
+    fn render(&self, mut w: &mut dyn std::io::Write, graph: &Graph) -> std::io::Result<()> {
+        render_coloured(&mut w, graph, &self.opts, &self.colours)
     }
+}
 
-    fn render<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
-        for line in &self.lines {
-            writeln!(w, "{}", line)?;
+/// Renders a DOT graph with a node for each owner, and an edge from one
+/// owner to another if a function of the second consumes data produced
+/// by a function of the first.
+struct OwnerGraphRenderer {
+    opts: RenderOptions,
+    colours: BTreeMap<String, String>,
+}
+
+impl Renderer for OwnerGraphRenderer {
+    fn render(&self, mut w: &mut dyn std::io::Write, graph: &Graph) -> std::io::Result<()> {
+        let mut g = DotBuilder::new(self.opts.compact);
+        g.add(format!("digraph {} {{", graph_name(self.opts.graph_name.as_deref().unwrap_or("G"))));
+        let nodes: Vec<Node> = self.colours
+            .iter()
+            .map(|(owner, colour)| {
+                let mut node = Node::new(owner);
+                node.shape(Shape::Box).style(Style::Filled).fillcolor(colour);
+                node
+            })
+            .collect();
+        g.add_node_group(&nodes);
+        for (from, to) in analysis::owner_dependencies(graph) {
+            g.add_edge(&Edge::new(from, to));
         }
-        Ok(())
+        g.add("}");
+        g.render(&mut w)
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-enum Shape {
-    Ellipse
+/// Renders a DOT graph of the data alone, with an edge from one data to
+/// another if a function consumes the first and produces the second.
+struct DataGraphRenderer {
+    opts: RenderOptions,
 }
 
-impl Shape {
-    fn to_string(self) -> String {
-        match self {
-            Shape::Ellipse => "ellipse".into()
+impl Renderer for DataGraphRenderer {
+    fn render(&self, mut w: &mut dyn std::io::Write, graph: &Graph) -> std::io::Result<()> {
+        let mut g = DotBuilder::new(self.opts.compact);
+        g.add(format!("digraph {} {{", graph_name(self.opts.graph_name.as_deref().unwrap_or("G"))));
+        let nodes: Vec<Node> = graph.data
+            .iter()
+            .map(|d| {
+                let mut node = Node::new(&d.name);
+                node.shape(self.opts.data_shape.unwrap_or(Shape::Box));
+                node
+            })
+            .collect();
+        g.add_node_group(&nodes);
+        for (from, to) in analysis::data_lineage(graph) {
+            g.add_edge(&Edge::new(from, to));
         }
+        g.add("}");
+        g.render(&mut w)
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-enum Style {
-    Filled
+#[test]
+fn data_graph() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+"#).unwrap();
+    let renderer = DataGraphRenderer { opts: RenderOptions::default() };
+
+    let mut out = Vec::new();
+    renderer.render(&mut out, &graph).unwrap();
+    let (nodes, edges) = summarise_dot(&String::from_utf8(out).unwrap());
+    assert_eq!(nodes.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+    let edges: Vec<(&str, &str)> = edges.iter().map(|(f, t, _)| (f.as_str(), t.as_str())).collect();
+    assert_eq!(edges, vec![("a", "b")]);
 }
 
-impl Style {
-    fn to_string(self) -> String {
-        match self {
-            Style::Filled => "filled".into()
+#[test]
+fn owner_graph() {
+    let graph = parse_graph(r#"
+data:
+  - {name: raw, source: s}
+  - {name: clean, source: s}
+  - {name: report, source: s}
+functions:
+  - {name: ingest, owner: Ingest, inputs: [], outputs: [raw]}
+  - {name: cleanse, owner: Ingest, inputs: [raw], outputs: [clean]}
+  - {name: summarise, owner: Reporting, inputs: [clean], outputs: [report]}
+"#).unwrap();
+    let renderer = OwnerGraphRenderer {
+        colours: owner_colours(&graph, &Palette::default()),
+        opts: RenderOptions::default(),
+    };
+
+    let mut out = Vec::new();
+    renderer.render(&mut out, &graph).unwrap();
+    let (nodes, edges) = summarise_dot(&String::from_utf8(out).unwrap());
+    assert_eq!(nodes.keys().collect::<Vec<_>>(), vec!["Ingest", "Reporting"]);
+    let edges: Vec<(&str, &str)> = edges.iter().map(|(f, t, _)| (f.as_str(), t.as_str())).collect();
+    assert_eq!(edges, vec![("Ingest", "Reporting")]);
+}
+
+#[test]
+fn renderer_per_format() {
+    let graph = parse_graph(include_str!("sample.yaml")).unwrap();
+    let render = |format: OutputFormat| {
+        let mut out = Vec::new();
+        format.renderer(RenderOptions::default(), &graph).render(&mut out, &graph).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(render(OutputFormat::Dot), render_to_string(&graph, &RenderOptions::default()));
+    let mut tree = Vec::new();
+    tree::render_tree(&mut tree, &graph).unwrap();
+    assert_eq!(render(OutputFormat::Tree).into_bytes(), tree);
+}
+
+/// Where to place the legend relative to the rest of the graph.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum LegendPosition {
+    Top,
+    Bottom,
+    Right
+}
+
+impl std::str::FromStr for LegendPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LegendPosition, String> {
+        match s {
+            "top" => Ok(LegendPosition::Top),
+            "bottom" => Ok(LegendPosition::Bottom),
+            "right" => Ok(LegendPosition::Right),
+            _ => Err(format!("unknown legend position '{}', expected top, bottom or right", s))
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-enum ColorScheme {
-    Dark28
+/// Parses a command line argument of the form `key=value`.
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    let mut parts = s.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(k), Some(v)) if !k.is_empty() && !v.is_empty() => Ok((k.into(), v.into())),
+        _ => Err(format!("expected key=value, got '{}'", s))
+    }
 }
 
-impl ColorScheme {
-    fn to_string(self) -> String {
-        match self {
-            ColorScheme::Dark28 => "dark28".into()
+/// Options controlling how a graph is rendered.
+#[derive(Clone, Debug, Default)]
+struct RenderOptions {
+    /// See `Cli::compact`.
+    compact: bool,
+    /// See `Cli::layout`.
+    layout: Option<Layout>,
+    /// See `Cli::layout_seed`.
+    layout_seed: Option<u64>,
+    /// See `Cli::splines`.
+    splines: Option<Splines>,
+    /// See `Cli::show_disabled`.
+    show_disabled: bool,
+    /// Arrowheads to use for each kind of flow, overriding the defaults.
+    arrowheads: HashMap<String, String>,
+    /// Name of the digraph, or "G" if not set.
+    graph_name: Option<String>,
+    /// Title of the legend, or "Legend" if not set.
+    legend_title: Option<String>,
+    /// See `Cli::legend_position`.
+    legend_position: Option<LegendPosition>,
+    /// See `Cli::legend_initials`.
+    legend_initials: bool,
+    /// Colours used to distinguish owners.
+    palette: Palette,
+    /// See `Cli::show_data_source`.
+    show_data_source: bool,
+    /// See `Cli::fragment`.
+    fragment: bool,
+    /// See `Cli::interactive`.
+    interactive: bool,
+    /// See `Cli::color_edges_by_source`.
+    color_edges_by_source: bool,
+    /// See `Cli::explain`.
+    explain: bool,
+    /// See `Cli::include_external`.
+    include_external: bool,
+    /// See `Cli::swimlanes`.
+    swimlanes: bool,
+    /// See `Cli::annotate_owners`.
+    annotate_owners: bool,
+    /// See `Cli::show_degree`.
+    show_degree: bool,
+    /// See `Cli::with_catalog`.
+    with_catalog: bool,
+    /// See `Cli::overview`.
+    overview: bool,
+    /// See `Cli::scale_by_degree`.
+    scale_by_degree: bool,
+    /// See `Cli::heatmap`.
+    heatmap: bool,
+    /// See `Cli::ports`.
+    ports: bool,
+    /// The position of each function in topological order, counting from
+    /// one, to show before its name. See `Cli::number_functions`.
+    function_numbers: HashMap<String, usize>,
+    /// See `Cli::group_edges`.
+    group_edges: bool,
+    /// See `Cli::merge_duplicates`.
+    merge_duplicates: bool,
+    /// See `Cli::cluster_owners`.
+    cluster_owners: bool,
+    /// See `Cli::highlight_owner`.
+    highlight_owner: Option<String>,
+    /// Changes to highlight when overlaying two versions of a graph.
+    overlay: diff::Overlay,
+    /// Short ids to use in place of node names, which are then only used
+    /// as labels. See `Cli::minify_ids`.
+    ids: HashMap<String, String>,
+    /// See `Cli::label_components`.
+    label_components: bool,
+    /// Shape of data nodes, or box if not set.
+    data_shape: Option<Shape>,
+    /// Shape of function nodes, or ellipse if not set.
+    function_shape: Option<Shape>,
+}
+
+impl RenderOptions {
+    /// The DOT id of the node for the data or function `name`, which is
+    /// only different from the name when minifying ids.
+    fn id<'a>(&'a self, name: &'a str) -> &'a str {
+        self.ids.get(name).map_or(name, String::as_str)
+    }
+
+    /// The DOT id of the node for function `f`, which is prefixed with its
+    /// owner when functions of several owners share its name.
+    fn function_id<'a>(&'a self, f: &'a Function) -> &'a str {
+        match self.ids.get(&owner_qualified_name(f)) {
+            Some(id) => id,
+            None => self.id(&f.name),
         }
     }
 
-    fn num_colors(self) -> usize {
-        match self {
-            ColorScheme::Dark28 => 8
+    /// The DOT ids of the nodes among `functions` and the data for `name`,
+    /// of which there are several when functions of different owners share
+    /// the name.
+    fn ids_named<'a>(&'a self, name: &'a str, functions: &[&'a Function]) -> Vec<&'a str> {
+        let mut ids: Vec<&str> = functions.iter().filter(|f| f.name == name).map(|f| self.function_id(f)).collect();
+        ids.sort();
+        ids.dedup();
+        if ids.is_empty() {
+            ids.push(self.id(name));
+        }
+        ids
+    }
+
+    /// The arrowhead used for edges with the given kind of flow, if any.
+    fn arrowhead(&self, kind: &str) -> Option<&str> {
+        if let Some(arrowhead) = self.arrowheads.get(kind) {
+            return Some(arrowhead);
+        }
+        match kind {
+            "read" => Some("normal"),
+            "write" => Some("diamond"),
+            "event" => Some("vee"),
+            _ => None
         }
     }
 }
 
-struct Node {
-    name: String,
-    attributes: HashMap<String, String>,
+/// Exit statuses for each class of failure, which scripts may rely on.
+/// Invalid command line arguments also exit with `FAILURE`.
+mod exit_code {
+    /// Any failure not covered below, e.g. an unreadable file.
+    pub const FAILURE: i32 = 1;
+    /// The spec isn't valid YAML, or doesn't have the expected structure.
+    pub const PARSE: i32 = 2;
+    /// The spec is inconsistent, e.g. refers to undefined data.
+    pub const VALIDATION: i32 = 3;
+    /// A check failed in strict mode, an --assert-* check or a limit such
+    /// as --max-depth failed, or the graph contains a cycle where one isn't
+    /// allowed.
+    pub const LINT: i32 = 4;
 }
 
-impl Node {
-    fn new(name: &str) -> Node {
-        Node {
-            name: name.to_string(),
-            attributes: HashMap::new()
+/// The exit status for `error`, from the first cause in its chain
+/// with a known class.
+fn exit_code(error: &failure::Error) -> i32 {
+    for cause in error.iter_chain() {
+        if cause.downcast_ref::<serde_yaml::Error>().is_some() {
+            return exit_code::PARSE;
+        }
+        if cause.downcast_ref::<checks::ValidationError>().is_some() {
+            return exit_code::VALIDATION;
+        }
+        if cause.downcast_ref::<checks::LintError>().is_some()
+            || cause.downcast_ref::<analysis::CycleError>().is_some() {
+            return exit_code::LINT;
         }
     }
+    exit_code::FAILURE
+}
 
-    fn style(&mut self, style: Style) -> &mut Self {
-        self.attribute("style", style.to_string())
+/// cargo run -- -i src/sample.yaml | dot -Tsvg >> sample.svg
+fn main() {
+    let args = Cli::from_args();
+    logger::init(args.verbose);
+    if let Err(error) = run(&args) {
+        let code = exit_code(&error);
+        eprintln!("Error: {:?}", ExitFailure::from(error));
+        std::process::exit(code);
     }
+}
 
-    fn shape(&mut self, shape: Shape) -> &mut Self {
-        self.attribute("shape", shape.to_string())
+fn run(args: &Cli) -> Result<(), failure::Error> {
+    let mut cache = SpecCache::new();
+    match &args.command {
+        Some(Command::Fmt { input, stdout }) => {
+            let formatted = format::canonical_yaml(&*cache.load(input)?)?;
+            if *stdout {
+                print!("{}", formatted);
+            } else {
+                std::fs::write(input, formatted)
+                    .with_context(
+                        |_| format!("could not write file '{:?}'", input)
+                    )?;
+            }
+            Ok(())
+        }
+        Some(Command::Hash { input }) => {
+            println!("{:016x}", format::canonical_hash(&*cache.load(input)?)?);
+            Ok(())
+        }
+        Some(Command::Diff { old, new, overlay: true }) => {
+            let (graph, overlay) = diff::overlay(&*cache.load(old)?, &*cache.load(new)?);
+            let renderer = DotRenderer {
+                colours: owner_colours(&graph, &Palette::default()),
+                opts: RenderOptions { overlay, ..Default::default() },
+            };
+            renderer.render(&mut std::io::stdout(), &graph)?;
+            Ok(())
+        }
+        Some(Command::Diff { old, new, overlay: false }) => {
+            let d = diff::diff(&*cache.load(old)?, &*cache.load(new)?);
+            if d.is_empty() {
+                println!("no changes");
+            } else {
+                print!("{}", d);
+            }
+            Ok(())
+        }
+        Some(Command::Schema) => {
+            println!("{}", spec_schema());
+            Ok(())
+        }
+        Some(Command::Batch { inputs, output_dir, continue_on_error, jobs }) => {
+            let mut outputs = HashMap::new();
+            for input in inputs {
+                let path = output_path(input, output_dir);
+                if let Some(other) = outputs.insert(path.clone(), input) {
+                    failure::bail!(
+                        "'{}' and '{}' would both be written to '{}'",
+                        other.display(),
+                        input.display(),
+                        path.display()
+                    );
+                }
+            }
+            std::fs::create_dir_all(output_dir)
+                .with_context(|_| format!("could not create directory '{:?}'", output_dir))?;
+            let mut failures = Vec::new();
+            for (input, result) in inputs.iter().zip(render_files(inputs, output_dir, *jobs, !*continue_on_error)) {
+                match result {
+                    Ok(path) => info!("wrote {}", path.display()),
+                    Err(error) if *continue_on_error => failures.push((input, error)),
+                    Err(error) => return Err(error),
+                }
+            }
+            if !*continue_on_error {
+                return Ok(());
+            }
+            println!("{} ok, {} failed", inputs.len() - failures.len(), failures.len());
+            for (input, error) in &failures {
+                let reasons: Vec<String> = error.iter_chain().map(|cause| cause.to_string()).collect();
+                println!("{}: {}", input.display(), reasons.join(": "));
+            }
+            if !failures.is_empty() {
+                failure::bail!("{} of {} specifications failed", failures.len(), inputs.len());
+            }
+            Ok(())
+        }
+        None => render_spec(args, &mut cache),
     }
+}
 
-    fn fillcolor(&mut self, color_scheme: ColorScheme, color: &str) -> &mut Self {
-        self.attribute(
-            "fillcolor",
-            format!("\"/{}/{}\"", color_scheme.to_string(), color)
-        )
+/// Downloads the text at `url`.
+#[cfg(feature = "remote")]
+fn fetch(url: &str) -> Result<String, failure::Error> {
+    let response = ureq::get(url)
+        .call()
+        .with_context(|_| format!("could not fetch '{}'", url))?;
+    let content_type = response.content_type().to_string();
+    let is_text = content_type.starts_with("text/")
+        || ["application/yaml", "application/x-yaml", "application/json"].contains(&content_type.as_str());
+    if !is_text {
+        failure::bail!("'{}' has content type '{}', expected text", url, content_type);
     }
+    Ok(response
+        .into_string()
+        .with_context(|_| format!("could not read response from '{}'", url))?)
+}
 
-    fn attribute<S: Into<String>, T: Into<String>>(
-        &mut self, name: S, value: T) -> &mut Self {
-        self.attributes.insert(name.into(), value.into());
-        self
-    }
+#[cfg(not(feature = "remote"))]
+fn fetch(url: &str) -> Result<String, failure::Error> {
+    failure::bail!("could not fetch '{}': flux was built without the remote feature", url)
+}
 
-    fn to_string(&self) -> String {
-        let mut attrs: Vec<String> = self
-            .attributes
-            .iter()
-            .map(|(k, v)| format!("{}={}", k, v))
-            .collect();
+#[cfg(all(test, feature = "remote"))]
+fn serve_once(content_type: &'static str, body: &'static str) -> String {
+    use std::io::{Read, Write};
 
-        attrs.sort();
-        format!("{}[{}];", self.name, attrs.join("="))
-    }
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/spec.yaml", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).unwrap();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            content_type,
+            body.len(),
+            body
+        ).unwrap();
+    });
+    url
 }
 
+#[cfg(feature = "remote")]
 #[test]
-fn node_to_string() {
-    let mut n = Node::new("foo");
-    let n = n
-        .shape(Shape::Ellipse)
-        .style(Style::Filled)
-        .fillcolor(ColorScheme::Dark28, "1");
-    assert_eq!(n.to_string(), "fff");
+fn spec_read_from_url() {
+    let url = serve_once("application/yaml", include_str!("sample.yaml"));
+    let graph = read_graph(std::path::Path::new(&url)).unwrap();
+    assert_eq!(graph, parse_graph(include_str!("sample.yaml")).unwrap());
+    assert!(render_to_string(&graph, &RenderOptions::default()).starts_with("digraph G {"));
+
+    let url = serve_once("image/png", "data: []\nfunctions: []\n");
+    let err = read_graph(std::path::Path::new(&url)).unwrap_err();
+    assert!(err.to_string().contains("expected text"), "{}", err);
 }
 
-fn render<W: std::io::Write>(w: &mut W, graph: &Graph) -> std::io::Result<()> {
-    // See https://www.graphviz.org/doc/info/colors.html for the definitions
-    // of the colour schemes. Functions are colored according to their owner,
-    // wrapping if we run out of colours.
-    const NUM_COLOURS: usize = 8;
-    const COLOUR_SCHEME: &str = "dark28";
+/// Reads and parses the dataflow specification at `path`.
+/// A path of `-` reads the specification from stdin.
+fn read_graph(path: &std::path::Path) -> Result<Graph, failure::Error> {
+    Ok(merge_fragments(read_fragments(path)?))
+}
 
-    let mut owners: Vec<String> = graph
-        .functions
-        .iter()
-        .map(|f| f.owner.clone())
-        .collect();
+/// Reads and parses the dataflow specification at `path` as `read_graph`
+/// does, but returns the graph of each YAML document without merging them.
+fn read_fragments(path: &std::path::Path) -> Result<Vec<Graph>, failure::Error> {
+    let url = path.to_str().filter(|p| p.starts_with("http://") || p.starts_with("https://"));
+    let fragments = if path.as_os_str() == "-" {
+        parse_fragments_reader(std::io::stdin().lock())
+    } else if let Some(url) = url {
+        parse_fragments_reader(fetch(url)?.as_bytes())
+    } else {
+        let file = std::fs::File::open(path)
+            .with_context(
+                |_| format!("could not read file '{:?}'", path)
+            )?;
+        parse_fragments_reader(std::io::BufReader::new(file))
+    };
 
-    owners.sort();
-    owners.dedup();
+    let fragments = fragments
+        .with_context(
+            |_| format!("could not parse file '{:?}'", path)
+        )?;
+
+    Ok(fragments)
+}
 
-    let colours: HashMap<String, String> = owners
+/// Loads the spec at `input` as `SpecCache::load` does, unless `input`
+/// is a glob pattern matching no file directly, in which case the specs
+/// in all files matching it are merged.
+fn load_spec(cache: &mut SpecCache, input: &std::path::Path) -> Result<Rc<Graph>, failure::Error> {
+    let pattern = match glob_pattern(input) {
+        Some(pattern) => pattern,
+        None => return cache.load(input),
+    };
+    let graphs = expand_glob(pattern)?
         .iter()
-        .enumerate()
-        .map(|(count, owner)| {
-            let c = count % NUM_COLOURS + 1;
-            //(owner.clone(), format!("\"/{}/{}\"", COLOUR_SCHEME, c))
-            (owner.clone(), c.to_string())
-        })
+        .map(|path| cache.load(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Rc::new(merge_graphs(graphs.iter().map(|g| &**g))))
+}
+
+/// Loads the fragments of the spec at `input` as `load_spec` would merge
+/// them: each document of each matching file. These aren't cached.
+fn load_fragments(input: &std::path::Path) -> Result<Vec<Graph>, failure::Error> {
+    let pattern = match glob_pattern(input) {
+        Some(pattern) => pattern,
+        None => return read_fragments(input),
+    };
+    let mut fragments = Vec::new();
+    for path in expand_glob(pattern)? {
+        fragments.extend(read_fragments(&path)?);
+    }
+    Ok(fragments)
+}
+
+/// Returns `input` if it's a glob pattern rather than the path of a file.
+fn glob_pattern(input: &std::path::Path) -> Option<&str> {
+    input.to_str().filter(|p| p.contains(|c| "*?[".contains(c)) && !input.exists())
+}
+
+/// Returns the files matching `pattern`, in alphabetical order. Fails if
+/// there are none.
+#[cfg(feature = "glob")]
+fn expand_glob(pattern: &str) -> Result<Vec<std::path::PathBuf>, failure::Error> {
+    let paths: Vec<std::path::PathBuf> = glob::glob(pattern)
+        .with_context(|_| format!("invalid pattern '{}'", pattern))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|_| format!("could not expand '{}'", pattern))?
+        .into_iter()
+        .filter(|p| p.is_file())
         .collect();
+    if paths.is_empty() {
+        failure::bail!("no files match '{}'", pattern);
+    }
+    Ok(paths)
+}
+
+#[cfg(not(feature = "glob"))]
+fn expand_glob(pattern: &str) -> Result<Vec<std::path::PathBuf>, failure::Error> {
+    failure::bail!("could not expand '{}': flux was built without the glob feature", pattern)
+}
 
-    let mut g = DotBuilder::new();
-    g.add("digraph G {{");
-    for d in &graph.data {
-        g.add(format!("{} [shape=box]", d.name));
+/// Combines spec fragments, e.g. one per service, into a single graph.
+/// Data declared identically in several fragments are only kept once, but
+/// differing declarations are all kept so that they're reported by
+/// `checks::conflicting_data`. Owner colours in legends are taken from the
+/// first fragment to give one.
+fn merge_graphs<'a>(graphs: impl IntoIterator<Item = &'a Graph>) -> Graph {
+    let mut merged = Graph { data: Vec::new(), functions: Vec::new(), legend: HashMap::new(), notes: Vec::new() };
+    for graph in graphs {
+        for d in &graph.data {
+            if !merged.data.contains(d) {
+                merged.data.push(d.clone());
+            }
+        }
+        merged.functions.extend(graph.functions.iter().cloned());
+        for (owner, colour) in &graph.legend {
+            merged.legend.entry(owner.clone()).or_insert_with(|| colour.clone());
+        }
+        merged.notes.extend(graph.notes.iter().cloned());
     }
+    merged
+}
 
-    for f in &graph.functions {
-        g.add_node(Node::new(&f.name)
-            .shape(Shape::Ellipse)
-            .style(Style::Filled)
-            .fillcolor(ColorScheme::Dark28, &colours[&f.owner])
-        );
-        // g.add(format!(
-        //     "{} [shape=ellipse,style=filled,fillcolor={}]",
-        //     f.name,
-        //     &colours[&f.owner]
-        // ));
-        for i in &f.inputs {
-            g.add(format!(
-                "{} -> {}", i, f.name
-            ));
+#[test]
+fn merged_graphs_share_data() {
+    let ingest = parse_graph(r#"
+data:
+  - {name: events, source: Web}
+  - {name: sessions, source: Ingest}
+functions:
+  - {name: sessionise, owner: Ingest, inputs: [events], outputs: [sessions]}
+"#).unwrap();
+    let report = parse_graph(r#"
+data:
+  - {name: sessions, source: Ingest}
+  - {name: report, source: Reporting}
+functions:
+  - {name: summarise, owner: Reporting, inputs: [sessions], outputs: [report]}
+"#).unwrap();
+
+    let merged = merge_graphs(&[ingest, report]);
+    let data: Vec<&str> = merged.data.iter().map(|d| d.name.as_str()).collect();
+    let functions: Vec<&str> = merged.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(data, vec!["events", "sessions", "report"]);
+    assert_eq!(functions, vec!["sessionise", "summarise"]);
+}
+
+#[cfg(feature = "glob")]
+#[test]
+fn glob_merges_matching_specs() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("a.yaml"),
+        "data: [{name: x, source: s}]\nfunctions: [{name: f, owner: o, inputs: [x], outputs: []}]\n"
+    ).unwrap();
+    std::fs::write(
+        dir.path().join("b.yaml"),
+        "data: [{name: x, source: s}]\nfunctions: [{name: g, owner: o, inputs: [x], outputs: []}]\n"
+    ).unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "not a spec").unwrap();
+
+    let mut cache = SpecCache::new();
+    let graph = load_spec(&mut cache, &dir.path().join("*.yaml")).unwrap();
+    assert_eq!(graph.data.len(), 1);
+    let functions: Vec<&str> = graph.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(functions, vec!["f", "g"]);
+
+    let err = load_spec(&mut cache, &dir.path().join("*.json")).unwrap_err();
+    assert!(err.to_string().starts_with("no files match"), "{}", err);
+}
+
+fn render_spec(args: &Cli, cache: &mut SpecCache) -> Result<(), failure::Error> {
+    let input = match &args.input {
+        Some(input) => input,
+        None => structopt::clap::Error::with_description(
+            "the --input argument is required",
+            structopt::clap::ErrorKind::MissingRequiredArgument
+        ).exit(),
+    };
+    let mut profile = Profile::new();
+    let mut fragments = Vec::new();
+    let mut graph = profile.time("parsing", || -> Result<Rc<Graph>, failure::Error> {
+        if !args.warn_mixed_roles {
+            return load_spec(cache, input);
         }
-        for o in &f.outputs {
-            g.add(format!(
-                "{} -> {}", f.name, o
-            ));
+        fragments = load_fragments(input)?;
+        Ok(Rc::new(merge_graphs(&fragments)))
+    })?;
+    debug!("parsed {} functions and {} data from {:?}", graph.functions.len(), graph.data.len(), input);
+    if args.dedupe_flows {
+        if let Err(error) = checks::duplicate_flows(&graph) {
+            warn!("ignoring {}", error);
+            graph = Rc::new(filter::without_duplicate_flows(&graph));
         }
     }
-    g.add("subgraph cluster_legend {{");
-    g.add("label=\"Legend\"");
-    g.add("rankdir=TB");
-    let mut ordering = String::new();
-    for (name, color) in &colours {
-        g.add(format!(
-            "legend_{} [label={},style=filled,fillcolor={}]",
-            name,
-            name,
-            color
-        ));
-        if !ordering.is_empty() {
-            ordering = ordering + "->";
+    profile.time("validation", || -> Result<(), checks::ValidationError> {
+        checks::empty_names(&graph)?;
+        if args.strict_schema {
+            checks::empty_sources(&graph)?;
+        }
+        checks::undefined_data(&graph)?;
+        checks::undefined_note_targets(&graph)?;
+        checks::duplicate_flows(&graph)
+    })?;
+    debug!("spec is valid");
+    let start = std::time::Instant::now();
+    if !args.filter_tags.is_empty() {
+        graph = Rc::new(filter::by_tags(&graph, &args.filter_tags));
+    }
+    if !args.exclude_data.is_empty() {
+        graph = Rc::new(filter::without_data(&graph, &args.exclude_data, args.prune_isolated));
+    }
+    if let Some(path) = &args.only_file {
+        let allowlist = std::fs::read_to_string(path)
+            .with_context(
+                |_| format!("could not read file '{:?}'", path)
+            )?;
+        graph = Rc::new(filter::by_names(&graph, &filter::parse_allowlist(&allowlist)));
+    }
+    if let Some(min) = args.min_owner_functions {
+        let (filtered, hidden) = filter::by_min_owner_functions(&graph, min);
+        if hidden > 0 {
+            info!("hid {} functions of owners with fewer than {} functions", hidden, min);
         }
-        ordering = format!("{}legend_{}", ordering, name);
+        graph = Rc::new(filtered);
     }
-    ordering = ordering + "[style=invis]";
-    g.add(format!("{}", ordering));
-    g.add("}}");
-    g.add("}}");
+    if args.collapse_chains {
+        graph = Rc::new(filter::collapse_chains(&graph));
+    }
+    debug!("selected {} functions and {} data to render", graph.functions.len(), graph.data.len());
+    profile.add("filtering", start.elapsed());
 
-    g.render(w)?;
+    profile.time("validation", || check(args, &graph))?;
+    if args.warn_mixed_roles {
+        let data = checks::mixed_roles(&fragments);
+        if !data.is_empty() {
+            report(args.strict, format!(
+                "data produced in one part of the spec but read as an external input in another: {}",
+                data.join(", ")
+            ))?;
+        }
+    }
+
+    let ids = if args.minify_ids { minified_ids(&graph) } else { disambiguated_ids(&graph) };
+    if let Some(path) = &args.id_map {
+        let lines: String = ids.iter().map(|(name, id)| format!("{}\t{}\n", id, name)).collect();
+        std::fs::write(path, lines)
+            .with_context(|_| format!("could not write file '{:?}'", path))?;
+    }
+    let opts = RenderOptions {
+        compact: args.compact,
+        layout: args.layout,
+        layout_seed: args.layout_seed,
+        splines: args.splines,
+        show_disabled: args.show_disabled,
+        arrowheads: args.arrowheads.iter().cloned().collect(),
+        graph_name: Some(args.graph_name.clone()),
+        legend_title: Some(args.legend_title.clone()),
+        legend_position: args.legend_position,
+        legend_initials: args.legend_initials,
+        palette: match &args.palette {
+            Some(palette) => palette.clone(),
+            None if args.colorblind_safe => Palette::Scheme(ColorScheme::OkabeIto),
+            None => Palette::default(),
+        },
+        show_data_source: args.show_data_source,
+        fragment: args.fragment,
+        interactive: args.interactive,
+        color_edges_by_source: args.color_edges_by_source,
+        explain: args.explain,
+        include_external: args.include_external,
+        swimlanes: args.swimlanes,
+        annotate_owners: args.annotate_owners,
+        show_degree: args.show_degree,
+        with_catalog: args.with_catalog,
+        overview: args.overview,
+        scale_by_degree: args.scale_by_degree,
+        heatmap: args.heatmap,
+        ports: args.ports,
+        function_numbers: if args.number_functions {
+            analysis::topological_order(&graph)?
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| (name, i + 1))
+                .collect()
+        } else {
+            HashMap::new()
+        },
+        group_edges: args.group_edges,
+        merge_duplicates: args.merge_duplicates,
+        cluster_owners: args.cluster_owners,
+        highlight_owner: args.highlight_owner.clone(),
+        overlay: diff::Overlay::default(),
+        ids: ids.iter().cloned().collect(),
+        label_components: args.label_components,
+        data_shape: Some(args.data_shape),
+        function_shape: Some(args.function_shape),
+    };
+    if opts.layout_seed.is_some() && !opts.layout.is_some_and(Layout::is_randomised) {
+        warn!("ignoring --layout-seed, which only affects the neato, fdp and sfdp layouts");
+    }
+    if let Some(owner) = &opts.highlight_owner {
+        if !graph.functions.iter().any(|f| &f.owner == owner) {
+            return Err(checks::ValidationError(format!("no functions have owner '{}'", owner)).into());
+        }
+    }
+    if let [from, to] = args.count_paths.as_slice() {
+        for node in &[from, to] {
+            let known = graph.data.iter().any(|d| &d.name == *node)
+                || graph.functions.iter().any(|f| &f.name == *node);
+            if !known {
+                return Err(checks::ValidationError(format!("unknown node '{}'", node)).into());
+            }
+        }
+        let (count, capped) = analysis::count_paths(&graph, from, to, MAX_PATHS);
+        if capped {
+            warn!("stopped counting at {} paths", MAX_PATHS);
+        }
+        println!("{}", count);
+        return Ok(());
+    }
+    if args.stats {
+        println!("functions: {}", graph.functions.len());
+        println!("data: {}", graph.data.len());
+        println!("longest path: {}", analysis::longest_path_len(&graph)?);
+        return Ok(());
+    }
+
+    if let (true, Some(dir)) = (args.split_by_owner, &args.output_dir) {
+        if args.format != OutputFormat::Dot {
+            failure::bail!("--split-by-owner only supports DOT output");
+        }
+        for path in render_by_owner(&graph, &opts, dir, args.jobs)? {
+            info!("wrote {}", path.display());
+        }
+        return Ok(());
+    }
 
+    let renderer = profile.time("colour assignment", || -> Result<Box<dyn Renderer>, failure::Error> {
+        if !args.owner_graph && !args.data_only {
+            return Ok(args.format.renderer(opts, &graph));
+        }
+        if args.format != OutputFormat::Dot {
+            failure::bail!("--owner-graph and --data-only only support DOT output");
+        }
+        if args.data_only {
+            return Ok(Box::new(DataGraphRenderer { opts }));
+        }
+        Ok(Box::new(OwnerGraphRenderer { colours: owner_colours(&graph, &opts.palette), opts }))
+    })?;
+    info!("rendering {} functions and {} data", graph.functions.len(), graph.data.len());
+    profile.time("rendering", || renderer.render(&mut std::io::stdout(), &graph))?;
+    if args.profile {
+        profile.report(&mut std::io::stderr())?;
+    }
     Ok(())
 }
 
-macro_rules! attrs {
-    ( $( $attr:expr => $value:expr),* ) => {
-        {
-            let mut vals = Vec::new();
-            $(
-                vals.push(format!("{}={}", $attr, $value));
-            )*
-            let res = vals.join(",");
-            format!("[{}]", res)
+/// Runs the checks requested by `args`, warning about or rejecting
+/// likely mistakes in `graph`.
+fn check(args: &Cli, graph: &Graph) -> Result<(), failure::Error> {
+    let conflicts = checks::conflicting_data(graph);
+    if !conflicts.is_empty() {
+        report(args.strict, format!(
+            "data declared more than once with different details: {}",
+            conflicts.join(", ")
+        ))?;
+    }
+
+    let clashes = checks::data_function_name_clash(graph);
+    if !clashes.is_empty() {
+        report(args.strict, format!(
+            "names used by both data and functions: {}",
+            clashes.join(", ")
+        ))?;
+    }
+
+    if args.warn_placeholders {
+        let placeholders = checks::placeholders(graph, &args.placeholders);
+        if !placeholders.is_empty() {
+            report(args.strict, format!("placeholder owners or sources: {}", placeholders.join(", ")))?;
+        }
+    }
+
+    if args.warn_self_dependencies {
+        let functions = checks::self_dependencies(graph);
+        if !functions.is_empty() {
+            report(args.strict, format!(
+                "functions reading their own outputs: {}",
+                functions.join(", ")
+            ))?;
+        }
+    }
+
+    if let Some(distance) = args.warn_similar_names {
+        let pairs: Vec<String> = checks::similar_names(graph, distance)
+            .iter()
+            .map(|(a, b)| format!("{} and {}", a, b))
+            .collect();
+        if !pairs.is_empty() {
+            report(args.strict, format!("similar names: {}", pairs.join(", ")))?;
+        }
+    }
+
+    if args.warn_dead_outputs {
+        let data = checks::dead_outputs(graph);
+        if !data.is_empty() {
+            report(args.strict, format!(
+                "data produced but never consumed: {}",
+                data.join(", ")
+            ))?;
+        }
+    }
+
+    if args.warn_shared_state {
+        let data = checks::shared_mutable_data(graph);
+        if !data.is_empty() {
+            report(args.strict, format!(
+                "data shared between several writers: {}",
+                data.join(", ")
+            ))?;
+        }
+    }
+
+    if let Some(max_depth) = args.max_depth {
+        checks::max_depth(graph, max_depth)?;
+    }
+
+    if let Some(max_edges) = args.max_edges {
+        checks::max_edges(graph, max_edges, args.show_disabled)?;
+    }
+
+    if args.assert_no_orphans {
+        let data = checks::orphans(graph);
+        if !data.is_empty() {
+            return Err(checks::LintError(format!(
+                "data not used by any function: {}; remove them from the spec \
+                 or add them to the inputs or outputs of the functions that use them",
+                data.join(", ")
+            )).into());
+        }
+    }
+
+    if args.require_unique_names {
+        let duplicates = checks::duplicate_names(graph);
+        if !duplicates.is_empty() {
+            return Err(checks::LintError(format!(
+                "names used more than once: {}",
+                duplicates.join(", ")
+            )).into());
+        }
+    }
+
+    if args.assert_connected {
+        let functions: Vec<&Function> = graph.functions.iter().filter(|f| f.is_enabled()).collect();
+        let components = analysis::components(&graph.data, &functions);
+        if components.len() > 1 {
+            let representatives: Vec<&str> = components.iter().map(|c| c[0]).collect();
+            return Err(checks::LintError(format!(
+                "graph has {} disconnected parts, one containing each of: {}",
+                components.len(),
+                representatives.join(", ")
+            )).into());
+        }
+    }
+
+    if let Some(path) = &args.allowed_owners {
+        let allowlist = std::fs::read_to_string(path)
+            .with_context(
+                |_| format!("could not read file '{:?}'", path)
+            )?;
+        let functions: Vec<String> = checks::unlisted_owners(graph, &filter::parse_allowlist(&allowlist))
+            .into_iter()
+            .map(|(function, owner)| format!("{} (owner '{}')", function, owner))
+            .collect();
+        if !functions.is_empty() {
+            return Err(checks::LintError(format!(
+                "functions with owners not in {:?}: {}",
+                path,
+                functions.join(", ")
+            )).into());
+        }
+    }
+
+    if args.assert_single_producer {
+        let data: Vec<String> = checks::multiple_producers(graph)
+            .iter()
+            .map(|(d, producers)| format!("{} (produced by {})", d, producers.join(", ")))
+            .collect();
+        if !data.is_empty() {
+            return Err(checks::LintError(format!(
+                "data with more than one producer: {}",
+                data.join(", ")
+            )).into());
+        }
+    }
+
+    if args.assert_has_producer {
+        let data = checks::unproduced(graph);
+        if !data.is_empty() {
+            return Err(checks::LintError(format!(
+                "data not produced by any function: {}",
+                data.join(", ")
+            )).into());
         }
     }
+    Ok(())
 }
 
-#[test]
-fn foo() {
-    let a = attrs!(
-        "label" => "foo",
-        "style" => "filled",
-        "fillcolor" => "red"
-    );
-    assert_eq!(a, String::from("[label=foo,style=filled,fillcolor=red]"));
+/// The most paths to count for --count-paths.
+const MAX_PATHS: usize = 100_000;
+
+/// Renders each of `inputs` as `render_file` does, using up to `jobs`
+/// threads, and returns the results in the same order as `inputs`. Each
+/// thread stops at its first failure if `stop_at_error` is set, so there may
+/// be fewer results than inputs.
+fn render_files(
+    inputs: &[std::path::PathBuf],
+    dir: &std::path::Path,
+    jobs: usize,
+    stop_at_error: bool
+) -> Vec<Result<std::path::PathBuf, failure::Error>> {
+    // Graphs are reference counted, so each thread has its own cache.
+    in_parallel(inputs, jobs, stop_at_error, SpecCache::new, |cache, input| render_file(cache, input, dir))
 }
 
-/// A dataflow graph.
-#[derive(Debug, Serialize, Deserialize)]
-struct Graph {
-    data: Vec<Data>,
-    functions: Vec<Function>,
+/// Applies `f` to each of `items` using up to `jobs` threads, each with
+/// its own state created by `init`, and returns the results in the same
+/// order as `items`. Each thread stops at its first failure if
+/// `stop_at_error` is set, so there may be fewer results than items.
+fn in_parallel<T: Sync, S, R: Send>(
+    items: &[T],
+    jobs: usize,
+    stop_at_error: bool,
+    init: impl Fn() -> S + Sync,
+    f: impl Fn(&mut S, &T) -> Result<R, failure::Error> + Sync
+) -> Vec<Result<R, failure::Error>> {
+    let apply_all = |items: &[T]| {
+        let mut state = init();
+        let mut results = Vec::new();
+        for item in items {
+            let result = f(&mut state, item);
+            let failed = result.is_err();
+            results.push(result);
+            if failed && stop_at_error {
+                break;
+            }
+        }
+        results
+    };
+    if jobs <= 1 || items.len() <= 1 {
+        return apply_all(items);
+    }
+
+    // Contiguous chunks keep the results in order when they're joined.
+    let chunk_size = items.len().div_ceil(jobs);
+    let apply_all = &apply_all;
+    std::thread::scope(|scope| {
+        let threads: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || apply_all(chunk)))
+            .collect();
+        let mut results = Vec::new();
+        for thread in threads {
+            let chunk_results = thread.join().expect("rendering thread panicked");
+            let failed = chunk_results.iter().any(Result::is_err);
+            results.extend(chunk_results);
+            if failed && stop_at_error {
+                break;
+            }
+        }
+        results
+    })
 }
 
-/// A piece of data in a dataflow graph.
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct Data {
-    /// The name of this data, as shown on the
-    /// rendered diagram.
-    name: String,
-    /// The name of the application or service that maintains
-    /// or provides this data.
-    source: String,
-    /// Human-readable description of this data.
-    description: Option<String>,
+/// Renders the specification at `input` to a DOT file of the same name in
+/// `dir`, returning the path of the file.
+fn render_file(
+    cache: &mut SpecCache,
+    input: &std::path::Path,
+    dir: &std::path::Path
+) -> Result<std::path::PathBuf, failure::Error> {
+    let graph = cache.load(input)?;
+    checks::empty_names(&graph)?;
+    checks::undefined_data(&graph)?;
+    checks::undefined_note_targets(&graph)?;
+    checks::duplicate_flows(&graph)?;
+    let path = output_path(input, dir);
+    let mut out = Vec::new();
+    DotRenderer {
+        colours: owner_colours(&graph, &Palette::default()),
+        opts: RenderOptions { ids: disambiguated_ids(&graph).into_iter().collect(), ..Default::default() },
+    }.render(&mut out, &graph)?;
+    std::fs::write(&path, out)
+        .with_context(|_| format!("could not write file '{:?}'", path))?;
+    Ok(path)
 }
 
-/// A process in a dataflow graph, i.e. a function.
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct Function {
-    /// The name of this function, as shown on the
-    /// rendered diagram.
-    name: String,
-    /// The process or service which performs this process.
-    owner: String,
-    /// Inputs to this function. To render a graph, each input needs
-    /// to have a corresponding Data instance.
-    inputs: Vec<String>,
-    /// Outputs from this function. To render a graph, each output needs
-    /// to have a corresponding Data instance.
-    outputs: Vec<String>,
+/// The file in `dir` to which `render_file` writes the rendering of
+/// `input`, named after it but with a .dot extension.
+fn output_path(input: &std::path::Path, dir: &std::path::Path) -> std::path::PathBuf {
+    let stem = input.file_stem().unwrap_or(input.as_os_str());
+    dir.join(format!("{}.dot", stem.to_string_lossy()))
+}
+
+/// Renders the functions of each owner in `graph`, and the data they consume
+/// or produce, to a DOT file in `dir` named after the owner, using up to
+/// `jobs` threads. Owners keep the colours they have in the full graph.
+/// Returns the paths written.
+fn render_by_owner(
+    graph: &Graph,
+    opts: &RenderOptions,
+    dir: &std::path::Path,
+    jobs: usize
+) -> Result<Vec<std::path::PathBuf>, failure::Error> {
+    let colours = owner_colours(graph, &opts.palette);
+    let owners: Vec<&str> = graph.functions
+        .iter()
+        .map(|f| f.owner.as_str())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    std::fs::create_dir_all(dir)
+        .with_context(|_| format!("could not create directory '{:?}'", dir))?;
+    let render_owner = |_: &mut (), owner: &&str| -> Result<std::path::PathBuf, failure::Error> {
+        let file_name: String = owner
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let path = dir.join(format!("{}.dot", file_name));
+        let mut out = Vec::new();
+        render_coloured(&mut out, &filter::by_owner(graph, owner), opts, &colours)?;
+        std::fs::write(&path, out)
+            .with_context(|_| format!("could not write file '{:?}'", path))?;
+        Ok(path)
+    };
+    in_parallel(&owners, jobs, true, || (), render_owner).into_iter().collect()
+}
+
+#[test]
+fn split_by_owner() {
+    let graph = parse_graph(r#"
+data:
+  - {name: raw, source: s}
+  - {name: clean, source: s}
+  - {name: report, source: s}
+functions:
+  - {name: cleanse, owner: Ingest, inputs: [raw], outputs: [clean]}
+  - {name: summarise, owner: Reporting team, inputs: [clean], outputs: [report]}
+"#).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    let paths = render_by_owner(&graph, &RenderOptions::default(), dir.path(), 1).unwrap();
+    assert_eq!(paths, vec![dir.path().join("Ingest.dot"), dir.path().join("Reporting_team.dot")]);
+    let serial: Vec<String> = paths.iter().map(|p| std::fs::read_to_string(p).unwrap()).collect();
+    let parallel_dir = tempfile::tempdir().unwrap();
+    let parallel_paths = render_by_owner(&graph, &RenderOptions::default(), parallel_dir.path(), 2).unwrap();
+    let parallel: Vec<String> = parallel_paths.iter().map(|p| std::fs::read_to_string(p).unwrap()).collect();
+    assert_eq!(parallel, serial);
+
+    let nodes = |path: &std::path::Path| -> Vec<String> {
+        let (nodes, _) = summarise_dot(&std::fs::read_to_string(path).unwrap());
+        nodes.keys().filter(|n| !n.contains("legend_")).cloned().collect()
+    };
+    assert_eq!(nodes(&paths[0]), vec!["clean", "cleanse", "raw"]);
+    assert_eq!(nodes(&paths[1]), vec!["clean", "report", "summarise"]);
+}
+
+/// Reports a problem found when checking a spec. This is
+/// an error in strict mode and a warning otherwise.
+fn report(strict: bool, message: String) -> Result<(), failure::Error> {
+    if strict {
+        return Err(checks::LintError(message).into());
+    }
+    warn!("{}", message);
+    Ok(())
+}
+
+struct DotBuilder {
+    lines: Vec<String>,
+    /// If true, elide optional whitespace and hoist attributes shared
+    /// by a group of nodes into a `node[...]` default statement.
+    compact: bool,
+}
+
+impl DotBuilder {
+    fn new(compact: bool) -> DotBuilder {
+        DotBuilder { lines: Vec::new(), compact }
+    }
+
+    fn add<S: Into<String>>(&mut self, line: S) {
+        self.lines.push(line.into());
+    }
+
+    fn add_node(&mut self, node: &Node) {
+        self.lines.push(node.to_string());
+    }
+
+    fn add_edge(&mut self, edge: &Edge) {
+        let arrow = if self.compact { "->" } else { " -> " };
+        self.lines.push(format!(
+            "{}{}{}{}",
+            endpoint(&edge.from, edge.from_port.as_deref()),
+            arrow,
+            endpoint(&edge.to, edge.to_port.as_deref()),
+            attribute_list(&edge.attributes)
+        ));
+    }
+
+    /// Adds a group of related nodes. In compact mode any attributes common
+    /// to all nodes in the group are emitted once, as defaults inside an
+    /// anonymous subgraph so that they don't leak into later statements.
+    fn add_node_group(&mut self, nodes: &[Node]) {
+        let mut shared: Vec<(&String, &String)> = match nodes.split_first() {
+            Some((first, rest)) if self.compact && !rest.is_empty() => first
+                .attributes
+                .iter()
+                .filter(|(k, v)| rest.iter().all(|n| n.attributes.get(*k) == Some(v)))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        if shared.is_empty() {
+            for node in nodes {
+                self.add_node(node);
+            }
+            return;
+        }
+
+        shared.sort();
+        let defaults: Vec<String> = shared
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        self.add("{");
+        self.add(format!("node[{}];", defaults.join(",")));
+        for node in nodes {
+            let mut node = node.clone();
+            for (k, _) in &shared {
+                node.attributes.remove(*k);
+            }
+            self.add_node(&node);
+        }
+        self.add("}");
+    }
+
+    fn render<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        for line in &self.lines {
+            writeln!(w, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Shape {
+    Box,
+    Circle,
+    Component,
+    Cylinder,
+    Diamond,
+    Ellipse,
+    Folder,
+    Hexagon,
+    Note,
+    Octagon,
+    Parallelogram,
+    Plaintext,
+    Record
+}
+
+impl Shape {
+    const ALL: [Shape; 13] = [
+        Shape::Box,
+        Shape::Circle,
+        Shape::Component,
+        Shape::Cylinder,
+        Shape::Diamond,
+        Shape::Ellipse,
+        Shape::Folder,
+        Shape::Hexagon,
+        Shape::Note,
+        Shape::Octagon,
+        Shape::Parallelogram,
+        Shape::Plaintext,
+        Shape::Record
+    ];
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Shape::Box => write!(f, "box"),
+            Shape::Circle => write!(f, "circle"),
+            Shape::Component => write!(f, "component"),
+            Shape::Cylinder => write!(f, "cylinder"),
+            Shape::Diamond => write!(f, "diamond"),
+            Shape::Ellipse => write!(f, "ellipse"),
+            Shape::Folder => write!(f, "folder"),
+            Shape::Hexagon => write!(f, "hexagon"),
+            Shape::Note => write!(f, "note"),
+            Shape::Octagon => write!(f, "octagon"),
+            Shape::Parallelogram => write!(f, "parallelogram"),
+            Shape::Plaintext => write!(f, "plaintext"),
+            Shape::Record => write!(f, "record")
+        }
+    }
+}
+
+impl std::str::FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Shape, String> {
+        Shape::ALL
+            .iter()
+            .find(|shape| shape.to_string() == s)
+            .cloned()
+            .ok_or_else(|| {
+                let known: Vec<String> = Shape::ALL.iter().map(|shape| shape.to_string()).collect();
+                format!("unknown shape '{}', expected one of {}", s, known.join(", "))
+            })
+    }
+}
+
+/// A Graphviz layout engine.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Layout {
+    Dot,
+    Neato,
+    Fdp,
+    Sfdp,
+    Circo,
+    Twopi,
+    Osage,
+    Patchwork
+}
+
+impl Layout {
+    const ALL: [Layout; 8] = [
+        Layout::Dot,
+        Layout::Neato,
+        Layout::Fdp,
+        Layout::Sfdp,
+        Layout::Circo,
+        Layout::Twopi,
+        Layout::Osage,
+        Layout::Patchwork
+    ];
+
+    /// Whether the layout starts from random node positions, which can be
+    /// fixed by setting the `start` attribute.
+    fn is_randomised(self) -> bool {
+        matches!(self, Layout::Neato | Layout::Fdp | Layout::Sfdp)
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Layout::Dot => write!(f, "dot"),
+            Layout::Neato => write!(f, "neato"),
+            Layout::Fdp => write!(f, "fdp"),
+            Layout::Sfdp => write!(f, "sfdp"),
+            Layout::Circo => write!(f, "circo"),
+            Layout::Twopi => write!(f, "twopi"),
+            Layout::Osage => write!(f, "osage"),
+            Layout::Patchwork => write!(f, "patchwork")
+        }
+    }
+}
+
+impl std::str::FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Layout, String> {
+        Layout::ALL
+            .iter()
+            .find(|l| l.to_string() == s)
+            .cloned()
+            .ok_or_else(|| {
+                let known: Vec<String> = Layout::ALL.iter().map(|l| l.to_string()).collect();
+                format!("unknown layout '{}', expected one of {}", s, known.join(", "))
+            })
+    }
+}
+
+/// A Graphviz style of edge routing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Splines {
+    Ortho,
+    Polyline,
+    Curved,
+    Line
+}
+
+impl Splines {
+    const ALL: [Splines; 4] = [
+        Splines::Ortho,
+        Splines::Polyline,
+        Splines::Curved,
+        Splines::Line
+    ];
+}
+
+impl fmt::Display for Splines {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Splines::Ortho => write!(f, "ortho"),
+            Splines::Polyline => write!(f, "polyline"),
+            Splines::Curved => write!(f, "curved"),
+            Splines::Line => write!(f, "line")
+        }
+    }
+}
+
+impl std::str::FromStr for Splines {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Splines, String> {
+        Splines::ALL
+            .iter()
+            .find(|splines| splines.to_string() == s)
+            .cloned()
+            .ok_or_else(|| {
+                let known: Vec<String> = Splines::ALL.iter().map(|splines| splines.to_string()).collect();
+                format!("unknown splines '{}', expected one of {}", s, known.join(", "))
+            })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Style {
+    Bold,
+    Dashed,
+    Filled
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Style::Bold => write!(f, "bold"),
+            Style::Dashed => write!(f, "dashed"),
+            Style::Filled => write!(f, "filled")
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum ColorScheme {
+    Dark28,
+    /// The colourblind-safe palette from Okabe and Ito, "Color Universal
+    /// Design", 2008. This isn't a Graphviz scheme, so its colours are
+    /// emitted explicitly. Black is omitted, as labels are drawn in black.
+    OkabeIto
+}
+
+const OKABE_ITO: [&str; 7] = [
+    "#E69F00", "#56B4E9", "#009E73", "#F0E442", "#0072B2", "#D55E00", "#CC79A7"
+];
+
+impl fmt::Display for ColorScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColorScheme::Dark28 => write!(f, "dark28"),
+            ColorScheme::OkabeIto => write!(f, "okabe-ito")
+        }
+    }
+}
+
+impl ColorScheme {
+    fn num_colors(self) -> usize {
+        match self {
+            ColorScheme::Dark28 => 8,
+            ColorScheme::OkabeIto => OKABE_ITO.len()
+        }
+    }
+
+    /// The colour at (zero-based) `index` in this scheme, which must be
+    /// less than `num_colors()`.
+    fn color(self, index: usize) -> String {
+        match self {
+            ColorScheme::OkabeIto => OKABE_ITO[index].to_string(),
+            _ => format!("/{}/{}", self, index + 1)
+        }
+    }
+}
+
+/// The colours used to distinguish owners.
+#[derive(Clone, Debug, PartialEq)]
+enum Palette {
+    /// A Graphviz colour scheme.
+    Scheme(ColorScheme),
+    /// An explicit list of colours, each either hex or a Graphviz colour name.
+    Colors(Vec<String>),
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::Scheme(ColorScheme::Dark28)
+    }
+}
+
+impl Palette {
+    /// The colour for the owner at `index` in the sorted list of
+    /// owners, wrapping if we run out of colours.
+    fn color(&self, index: usize) -> String {
+        match self {
+            Palette::Scheme(scheme) => scheme.color(index % scheme.num_colors()),
+            Palette::Colors(colors) => colors[index % colors.len()].clone(),
+        }
+    }
+
+    /// The number of colours before `color` wraps.
+    fn len(&self) -> usize {
+        match self {
+            Palette::Scheme(scheme) => scheme.num_colors(),
+            Palette::Colors(colors) => colors.len(),
+        }
+    }
+}
+
+/// Checks that `color` is either hex, e.g. `#1f77b4`, or a colour name.
+fn check_colour(color: &str) -> Result<(), String> {
+    let is_hex = color.starts_with('#')
+        && [4, 7, 9].contains(&color.len())
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    let is_name = color.starts_with(|c: char| c.is_ascii_alphabetic())
+        && color.chars().all(|c| c.is_ascii_alphanumeric());
+    if !is_hex && !is_name {
+        return Err(format!("'{}' is not a hex colour or colour name", color));
+    }
+    Ok(())
+}
+
+impl std::str::FromStr for Palette {
+    type Err = String;
+
+    /// Parses a comma-separated list of colours, e.g. `#1f77b4,#ff7f0e,red`.
+    fn from_str(s: &str) -> Result<Palette, String> {
+        let colors: Vec<String> = s.split(',').map(|c| c.trim().to_string()).collect();
+        for color in &colors {
+            check_colour(color)?;
+        }
+        Ok(Palette::Colors(colors))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    name: String,
+    attributes: HashMap<String, String>,
+}
+
+impl Node {
+    fn new(name: &str) -> Node {
+        Node {
+            name: name.to_string(),
+            attributes: HashMap::new()
+        }
+    }
+
+    /// Adds `style` to the node, along with any style already set.
+    fn style(&mut self, style: Style) -> &mut Self {
+        let style = match self.attributes.get("style") {
+            Some(existing) => format!("{},{}", existing.trim_matches('"'), style),
+            None => style.to_string()
+        };
+        self.attribute("style", style)
+    }
+
+    fn shape(&mut self, shape: Shape) -> &mut Self {
+        self.attribute("shape", shape.to_string())
+    }
+
+    fn fillcolor(&mut self, color: &str) -> &mut Self {
+        self.attribute("fillcolor", color)
+    }
+
+    /// Styles this node to show how it changed between two versions of a
+    /// graph.
+    fn change(&mut self, change: diff::Change) -> &mut Self {
+        match change {
+            diff::Change::Added => self.attribute("color", "green").attribute("penwidth", "2"),
+            diff::Change::Removed => self.attribute("color", "red").style(Style::Dashed),
+            diff::Change::Modified => self.attribute("color", "orange").attribute("penwidth", "2"),
+        }
+    }
+
+    /// Makes this node a hyperlink in SVG output.
+    fn url(&mut self, url: &str) -> &mut Self {
+        self.attribute("URL", url)
+    }
+
+    /// Sets an attribute, quoting the value unless it's an identifier or
+    /// numeral.
+    fn attribute<S: Into<String>, T: AsRef<str>>(
+        &mut self, name: S, value: T) -> &mut Self {
+        self.raw_attribute(name, dot_id(value.as_ref()))
+    }
+
+    /// Sets an attribute to a value which is already valid DOT, e.g. a
+    /// quoted string containing escape sequences.
+    fn raw_attribute<S: Into<String>, T: Into<String>>(
+        &mut self, name: S, value: T) -> &mut Self {
+        self.attributes.insert(name.into(), value.into());
+        self
+    }
+
+    /// Adds attributes given verbatim in a spec.
+    /// These never replace attributes which are already set.
+    fn extra_attributes(&mut self, attributes: &HashMap<String, String>) -> &mut Self {
+        for (name, value) in attributes {
+            self.attributes.entry(dot_id(name)).or_insert_with(|| dot_id(value));
+        }
+        self
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{};", dot_id(&self.name), attribute_list(&self.attributes))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Edge {
+    from: String,
+    from_port: Option<String>,
+    to: String,
+    to_port: Option<String>,
+    attributes: HashMap<String, String>,
+}
+
+impl Edge {
+    fn new(from: &str, to: &str) -> Edge {
+        Edge {
+            from: from.to_string(),
+            from_port: None,
+            to: to.to_string(),
+            to_port: None,
+            attributes: HashMap::new()
+        }
+    }
+
+    /// Sets the ports at which the edge leaves `from` and enters `to`.
+    fn ports(&mut self, from: Option<&str>, to: Option<&str>) -> &mut Self {
+        self.from_port = from.map(str::to_string);
+        self.to_port = to.map(str::to_string);
+        self
+    }
+
+    /// Sets an attribute, quoting the value unless it's an identifier or
+    /// numeral.
+    fn attribute<S: Into<String>, T: AsRef<str>>(
+        &mut self, name: S, value: T) -> &mut Self {
+        self.attributes.insert(name.into(), dot_id(value.as_ref()));
+        self
+    }
+}
+
+/// Converts `name` to a DOT identifier, replacing characters that aren't
+/// letters, digits or underscores, and prefixing names starting with a
+/// digit with an underscore. Keywords are quoted.
+fn graph_name(name: &str) -> String {
+    let mut id: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if id.is_empty() || id.starts_with(|c: char| c.is_ascii_digit()) {
+        id.insert(0, '_');
+    }
+    dot_id(&id)
+}
+
+#[test]
+fn graph_names() {
+    let graph = parse_graph(include_str!("sample.yaml")).unwrap();
+    let first_line = |name: Option<&str>| {
+        let opts = RenderOptions { graph_name: name.map(String::from), ..Default::default() };
+        render_to_string(&graph, &opts).lines().next().unwrap().to_string()
+    };
+
+    assert_eq!(first_line(None), "digraph G {");
+    assert_eq!(first_line(Some("Pipeline_2")), "digraph Pipeline_2 {");
+    assert_eq!(first_line(Some("my pipeline-v2")), "digraph my_pipeline_v2 {");
+    assert_eq!(first_line(Some("2nd")), "digraph _2nd {");
+    assert_eq!(first_line(Some("graph")), "digraph \"graph\" {");
+}
+
+/// Returns a short id, n1, n2, ..., for the name of each data and function
+/// in `graph`, in the order they're defined. Functions of different owners
+/// which share a name are given an id each, keyed as in `disambiguated_ids`.
+fn minified_ids(graph: &Graph) -> Vec<(String, String)> {
+    let shared = shared_function_names(graph);
+    let names = graph.data.iter().map(|d| d.name.clone()).chain(graph.functions.iter().map(|f| {
+        if shared.contains(f.name.as_str()) { owner_qualified_name(f) } else { f.name.clone() }
+    }));
+    let mut seen = HashSet::new();
+    names
+        .filter(|name| seen.insert(name.clone()))
+        .enumerate()
+        .map(|(i, name)| (name, format!("n{}", i + 1)))
+        .collect()
+}
+
+/// Returns the names of functions which are given to functions of more
+/// than one owner.
+fn shared_function_names(graph: &Graph) -> HashSet<&str> {
+    let mut owners: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for f in &graph.functions {
+        owners.entry(&f.name).or_default().insert(&f.owner);
+    }
+    owners.into_iter().filter(|(_, owners)| owners.len() > 1).map(|(name, _)| name).collect()
+}
+
+/// The name of function `f` prefixed with its owner, e.g. `Search/load`.
+fn owner_qualified_name(f: &Function) -> String {
+    format!("{}/{}", f.owner, f.name)
+}
+
+/// Returns `id`, followed by as many underscores as are needed for it to
+/// differ from all of `taken`.
+fn unused_id(mut id: String, taken: &HashSet<&str>) -> String {
+    while taken.contains(id.as_str()) {
+        id.push('_');
+    }
+    id
+}
+
+/// Returns an owner-prefixed id for each function whose name is shared
+/// with a function of another owner, so that each is drawn as a separate
+/// node rather than all being merged into one.
+fn disambiguated_ids(graph: &Graph) -> Vec<(String, String)> {
+    let shared = shared_function_names(graph);
+    let mut seen = HashSet::new();
+    graph.functions
+        .iter()
+        .filter(|f| shared.contains(f.name.as_str()))
+        .map(owner_qualified_name)
+        .filter(|name| seen.insert(name.clone()))
+        .map(|name| (name.clone(), name))
+        .collect()
+}
+
+#[test]
+fn shared_function_names_drawn_separately() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: load, owner: Search, inputs: [a], outputs: []}
+  - {name: load, owner: Billing, inputs: [a], outputs: [b]}
+  - {name: index, owner: Search, inputs: [b], outputs: []}
+"#).unwrap();
+
+    let ids: HashMap<String, String> = disambiguated_ids(&graph).into_iter().collect();
+    assert_eq!(ids.len(), 2);
+    let out = render_to_string(&graph, &RenderOptions { ids, fragment: true, ..Default::default() });
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes["\"Search/load\""]["label"], "\"load\"");
+    assert_eq!(nodes["\"Billing/load\""]["label"], "\"load\"");
+    assert!(!nodes.contains_key("load"));
+    assert_eq!(nodes["index"]["label"], "\"index\"");
+    let edges: Vec<(&str, &str)> = edges.iter().map(|(from, to, _)| (from.as_str(), to.as_str())).collect();
+    assert_eq!(edges, vec![("\"Billing/load\"", "b"), ("a", "\"Billing/load\""), ("a", "\"Search/load\""), ("b", "index")]);
+}
+
+#[test]
+fn minified_ids_shorten_nodes() {
+    let graph = parse_graph(r#"
+data:
+  - {name: customer orders, source: s}
+  - {name: invoices, source: s}
+functions:
+  - {name: generate invoices, owner: Billing, inputs: [customer orders], outputs: [invoices]}
+"#).unwrap();
+
+    let ids: HashMap<String, String> = minified_ids(&graph).into_iter().collect();
+    assert_eq!(ids["customer orders"], "n1");
+    assert_eq!(ids["generate invoices"], "n3");
+    let out = render_to_string(&graph, &RenderOptions { ids, ..Default::default() });
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes["n1"]["label"], "\"customer orders\"");
+    assert_eq!(nodes["n2"]["label"], "\"invoices\"");
+    assert_eq!(nodes["n3"]["label"], "\"generate invoices\"");
+    let edges: Vec<(&str, &str)> = edges.iter().map(|(from, to, _)| (from.as_str(), to.as_str())).collect();
+    assert_eq!(edges, vec![("n1", "n3"), ("n3", "n2")]);
+    assert!(!out.contains("\"customer orders\" ->"));
+}
+
+/// The fill colour of a node with the given metric in a heatmap of metrics
+/// from `min` to `max`, as a Graphviz HSV colour with hue from green for
+/// `min` to red for `max`.
+fn heat_colour(metric: f64, min: f64, max: f64) -> String {
+    let heat = if max > min { (metric - min) / (max - min) } else { 0.0 };
+    format!("{:.3} 0.700 0.900", (1.0 - heat) / 3.0)
+}
+
+/// The first letter of each word of `owner`, capitalised, where words are
+/// separated by punctuation, spaces or a change from lower to upper case,
+/// e.g. "DP" for "Data Platform", "dataPlatform" or "data-platform".
+fn initials(owner: &str) -> String {
+    let mut initials = String::new();
+    let mut previous: Option<char> = None;
+    for c in owner.chars() {
+        let starts_word = c.is_alphanumeric() && match previous {
+            None => true,
+            Some(p) => !p.is_alphanumeric() || (p.is_lowercase() && c.is_uppercase()),
+        };
+        if starts_word {
+            initials.extend(c.to_uppercase());
+        }
+        previous = Some(c);
+    }
+    if initials.is_empty() {
+        owner.to_string()
+    } else {
+        initials
+    }
+}
+
+#[test]
+fn owner_initials() {
+    assert_eq!(initials("Data Platform"), "DP");
+    assert_eq!(initials("platform/ingest"), "PI");
+    assert_eq!(initials("searchRanking_v2"), "SRV");
+    assert_eq!(initials("Billing"), "B");
+    assert_eq!(initials("--"), "--");
+}
+
+/// The SVG class of the nodes and edges of functions owned by `owner`,
+/// e.g. "owner_platform_ingest" for "platform/ingest".
+fn owner_class(owner: &str) -> String {
+    let owner: String = owner
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+    format!("owner_{}", owner)
+}
+
+#[test]
+fn interactive_nodes_have_owner_classes() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: platform/ingest, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { interactive: true, ..Default::default() });
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes["f"]["class"], "\"function owner_platform_ingest\"");
+    assert_eq!(nodes["a"]["class"], "data");
+    assert_eq!(nodes["\"legend_platform/ingest\""]["id"], "legend_owner_platform_ingest");
+    assert_eq!(nodes["\"legend_platform/ingest\""]["class"], "\"legend owner_platform_ingest\"");
+    assert!(edges.contains(&("a".into(), "f".into(), r#"{"class": "owner_platform_ingest"}"#.into())));
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert!(!out.contains("class="));
+}
+
+/// Formats an edge endpoint, qualifying the node with a port if given.
+fn endpoint(node: &str, port: Option<&str>) -> String {
+    match port {
+        Some(port) => format!("{}:{}", dot_id(node), port),
+        None => dot_id(node)
+    }
+}
+
+/// Compass points, which name ports on nodes of any shape.
+const COMPASS_POINTS: [&str; 10] = ["n", "ne", "e", "se", "s", "sw", "w", "nw", "c", "_"];
+
+/// Label for a record-shaped node, with `text` (already escaped for a
+/// quoted string) above a row of fields for the named (non-compass) ports
+/// that edges attach to.
+fn record_label<'a>(text: &str, ports: impl Iterator<Item = &'a str>) -> String {
+    let fields: BTreeSet<&str> = ports.filter(|p| !COMPASS_POINTS.contains(p)).collect();
+    let mut label = escape_record(text);
+    if !fields.is_empty() {
+        let fields: Vec<String> = fields.iter().map(|p| format!("<{}> {}", p, p)).collect();
+        label = format!("{{{}|{{{}}}}}", label, fields.join("|"));
+    }
+    format!("\"{}\"", label)
+}
+
+/// Escapes a string for inclusion in an HTML-like label.
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// An HTML-like label for a table with a row for each of `data`, giving
+/// its name, source and description.
+fn catalog_label(data: &[Data]) -> String {
+    let mut rows = vec!["<tr><td><b>Data</b></td><td><b>Source</b></td><td><b>Description</b></td></tr>".to_string()];
+    for d in data {
+        rows.push(format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&d.name),
+            escape_html(&d.source),
+            escape_html(d.description.as_deref().unwrap_or(""))
+        ));
+    }
+    format!("<<table border=\"0\" cellborder=\"1\" cellspacing=\"0\">{}</table>>", rows.concat())
+}
+
+/// Escapes record field separators in `text`, which is already escaped for
+/// a quoted string.
+fn escape_record(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "{}|<>".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The port on a function node drawn with --ports for its `i`th input or
+/// output.
+fn flow_port(input: bool, i: usize) -> String {
+    format!("{}{}", if input { "in" } else { "out" }, i)
+}
+
+/// Label for a function node drawn with --ports, with `text` between a row
+/// of fields for its inputs and a row of fields for its outputs.
+fn ports_label(text: &str, f: &Function) -> String {
+    let row = |flows: &[Flow], input: bool| -> Option<String> {
+        if flows.is_empty() {
+            return None;
+        }
+        let fields: Vec<String> = flows
+            .iter()
+            .enumerate()
+            .map(|(i, flow)| format!("<{}> {}", flow_port(input, i), escape_record(&escape(&flow.name))))
+            .collect();
+        Some(format!("{{{}}}", fields.join("|")))
+    };
+    let rows: Vec<String> = row(&f.inputs, true)
+        .into_iter()
+        .chain(Some(escape_record(text)))
+        .chain(row(&f.outputs, false))
+        .collect();
+    format!("\"{{{}}}\"", rows.join("|"))
+}
+
+/// Formats `attributes` as a DOT attribute list, sorted by name,
+/// or returns an empty string if there are no attributes.
+fn attribute_list(attributes: &HashMap<String, String>) -> String {
+    if attributes.is_empty() {
+        return String::new();
+    }
+    let mut attrs: Vec<String> = attributes
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+    attrs.sort();
+    format!("[{}]", attrs.join(","))
+}
+
+/// Wraps a string in double quotes, escaping any characters that
+/// would otherwise terminate or corrupt the quoted DOT string.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+/// Returns `name` as a DOT ID, quoting it unless it's a valid identifier or
+/// numeral. DOT keywords are also quoted, as they'd otherwise be misparsed.
+fn dot_id(name: &str) -> String {
+    const KEYWORDS: [&str; 6] = ["node", "edge", "graph", "digraph", "subgraph", "strict"];
+
+    let is_identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let is_numeral = name.parse::<f64>().is_ok()
+        && name.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '-');
+    let is_keyword = KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(name));
+
+    if (is_identifier || is_numeral) && !is_keyword {
+        name.to_string()
+    } else {
+        quote(name)
+    }
+}
+
+/// Escapes a string for inclusion in a quoted DOT string.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[test]
+fn dot_ids() {
+    assert_eq!(dot_id("Position_2"), "Position_2");
+    assert_eq!(dot_id("-1.5"), "-1.5");
+    assert_eq!(dot_id("2nd"), r#""2nd""#);
+    assert_eq!(dot_id("user id"), r#""user id""#);
+    assert_eq!(dot_id("a\"b"), r#""a\"b""#);
+}
+
+#[test]
+fn attribute_values_quoted_when_needed() {
+    let mut node = Node::new("n");
+    node.attribute("label", "two words")
+        .attribute("fontsize", "20")
+        .attribute("tooltip", "say \"hi\"")
+        .attribute("color", "red");
+    assert_eq!(
+        node.to_string(),
+        r#"n[color=red,fontsize=20,label="two words",tooltip="say \"hi\""];"#
+    );
+
+    let mut edge = Edge::new("a", "b");
+    edge.attribute("label", "p99 50ms");
+    assert_eq!(edge.attributes["label"], r#""p99 50ms""#);
+}
+
+#[test]
+fn descriptions_emitted_as_comments() {
+    let graph = parse_graph(r#"
+data:
+  - {name: orders, source: Shop, description: 'Orders placed on the "new" site'}
+  - {name: invoices, source: Billing}
+functions:
+  - {name: bill, owner: Billing, description: Bills each order, inputs: [orders], outputs: [invoices]}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["orders"]["comment"], r#""Orders placed on the \"new\" site""#);
+    assert_eq!(nodes["bill"]["comment"], r#""Bills each order""#);
+    assert!(!nodes["invoices"].contains_key("comment"));
+}
+
+#[test]
+fn overview_summarises_owners() {
+    let graph = parse_graph(r#"
+data:
+  - {name: raw, source: s}
+  - {name: clean, source: s}
+  - {name: report, source: s}
+functions:
+  - {name: ingest, owner: Ingest, inputs: [], outputs: [raw]}
+  - {name: cleanse, owner: Ingest, inputs: [raw], outputs: [clean]}
+  - {name: summarise, owner: Reporting, inputs: [clean], outputs: [report]}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { overview: true, ..Default::default() });
+    let (nodes, edges) = summarise_dot(&out);
+    for name in &["raw", "clean", "report", "ingest", "cleanse", "summarise"] {
+        assert!(nodes.contains_key(*name), "{}", out);
+    }
+    let overview = &out[out.find("subgraph cluster_overview {").unwrap()..];
+    let overview = &overview[..overview.find("\n}\n").unwrap()];
+    assert!(overview.contains("overview_Ingest[fillcolor="), "{}", overview);
+    assert!(overview.contains("overview_Reporting[fillcolor="), "{}", overview);
+    assert_eq!(nodes["overview_Ingest"]["label"], "Ingest");
+    assert_eq!(nodes["overview_Ingest"]["fillcolor"], nodes["ingest"]["fillcolor"]);
+    assert!(edges.contains(&("overview_Ingest".into(), "overview_Reporting".into(), "{}".into())));
+    assert!(!edges.iter().any(|(from, to, _)| from == "overview_Reporting" || to == "overview_Ingest"));
+}
+
+#[test]
+fn overview_ids_avoid_node_names() {
+    let graph = parse_graph(r#"
+data:
+  - {name: overview_o, source: s}
+functions:
+  - {name: f, owner: o, inputs: [overview_o], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { overview: true, ..Default::default() });
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["overview_o"]["shape"], "box");
+    assert!(!nodes["overview_o"].contains_key("label"));
+    assert_eq!(nodes["overview_o_"]["label"], "o");
+    let overview = &out[out.find("subgraph cluster_overview {").unwrap()..];
+    let overview = &overview[..overview.find("\n}\n").unwrap()];
+    assert!(!overview.contains("\noverview_o["), "{}", overview);
+}
+
+#[test]
+fn catalog_lists_data() {
+    let graph = parse_graph(r#"
+data:
+  - {name: orders, source: Shop, description: "Orders < 1 day old"}
+  - {name: invoices, source: Billing}
+functions:
+  - {name: bill, owner: Billing, inputs: [orders], outputs: [invoices]}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert!(!out.contains("catalog"));
+
+    let out = render_to_string(&graph, &RenderOptions { with_catalog: true, ..Default::default() });
+    let (nodes, _) = summarise_dot(&out);
+    let label = &nodes["catalog"]["label"];
+    assert!(label.contains("<tr><td>orders</td><td>Shop</td><td>Orders &lt; 1 day old</td></tr>"), "{}", label);
+    assert!(label.contains("<tr><td>invoices</td><td>Billing</td><td></td></tr>"), "{}", label);
+    assert_eq!(nodes["catalog"]["shape"], "plaintext");
+    assert!(out.contains("{rank=sink;catalog}"));
+}
+
+#[test]
+fn catalog_and_legend_ids_avoid_node_names() {
+    let graph = parse_graph(r#"
+data:
+  - {name: catalog, source: Shop}
+  - {name: legend_Billing, source: Billing}
+functions:
+  - {name: bill, owner: Billing, inputs: [catalog], outputs: [legend_Billing]}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { with_catalog: true, ..Default::default() });
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes["catalog"]["shape"], "box");
+    assert_eq!(nodes["catalog_"]["shape"], "plaintext");
+    assert!(out.contains("{rank=sink;catalog_}"));
+    assert_eq!(nodes["legend_Billing"]["shape"], "box");
+    assert_eq!(nodes["legend_Billing_"]["label"], "Billing");
+    assert!(edges.contains(&("bill".into(), "legend_Billing".into(), "{}".into())));
+}
+
+#[test]
+fn notes_drawn_and_linked() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: []}
+  - {name: g, owner: o, inputs: [a], outputs: [], enabled: false}
+notes:
+  - {text: "Runs nightly, see the runbook", near: f}
+  - {text: "Being replaced", near: g}
+  - {text: "All times are UTC"}
+"#).unwrap();
+
+    let (nodes, edges) = summarise_dot(&render_to_string(&graph, &RenderOptions::default()));
+    assert_eq!(nodes["note_1"]["shape"], "note");
+    assert_eq!(nodes["note_1"]["label"], "\"Runs nightly, see the runbook\"");
+    assert!(edges.contains(&("note_1".into(), "f".into(), r#"{"arrowhead": "none", "style": "dashed"}"#.into())));
+    assert!(!nodes.contains_key("note_2"));
+    assert_eq!(nodes["note_3"]["shape"], "note");
+    assert!(!edges.iter().any(|(from, _, _)| from == "note_3"));
+}
+
+#[test]
+fn note_ids_avoid_node_names() {
+    let graph = parse_graph(r#"
+data:
+  - {name: note_1, source: s}
+  - {name: note_1_, source: s}
+functions:
+  - {name: f, owner: o, inputs: [note_1], outputs: [note_1_]}
+notes:
+  - {text: "Runs nightly", near: f}
+"#).unwrap();
+
+    let (nodes, edges) = summarise_dot(&render_to_string(&graph, &RenderOptions::default()));
+    assert_eq!(nodes["note_1"]["shape"], "box");
+    assert_eq!(nodes["note_1__"]["shape"], "note");
+    assert!(edges.contains(&("note_1__".into(), "f".into(), r#"{"arrowhead": "none", "style": "dashed"}"#.into())));
+    assert!(edges.contains(&("note_1".into(), "f".into(), "{}".into())));
+}
+
+#[test]
+fn legend_labelled_with_initials() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: Customer Data Platform, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { legend_initials: true, ..Default::default() });
+    let (nodes, _) = summarise_dot(&out);
+    let entry = &nodes["\"legend_Customer Data Platform\""];
+    assert_eq!(entry["label"], "CDP");
+    assert_eq!(entry["tooltip"], "\"Customer Data Platform\"");
+}
+
+#[test]
+fn legend_labels_with_spaces_are_quoted() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: Data team, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let (nodes, _) = summarise_dot(&render_to_string(&graph, &RenderOptions::default()));
+    assert_eq!(nodes["\"legend_Data team\""]["label"], "\"Data team\"");
+}
+
+#[test]
+fn reserved_keywords_are_quoted() {
+    for keyword in &["node", "edge", "graph", "digraph", "subgraph", "strict", "Node"] {
+        let data = parse_graph(&format!(r#"
+data:
+  - {{name: {}, source: s}}
+functions:
+  - {{name: f, owner: o, inputs: [{0}], outputs: [{0}]}}
+"#, keyword)).unwrap();
+        let out = render_to_string(&data, &RenderOptions::default());
+        assert!(out.contains(&format!("\n\"{}\"[shape=box];\n", keyword)), "{}", out);
+        assert!(out.contains(&format!("\n\"{}\" -> f\n", keyword)), "{}", out);
+        assert!(out.contains(&format!("\nf -> \"{}\"\n", keyword)), "{}", out);
+
+        let function = parse_graph(&format!(r#"
+data:
+  - {{name: a, source: s}}
+functions:
+  - {{name: {}, owner: o, inputs: [a], outputs: [a]}}
+"#, keyword)).unwrap();
+        let out = render_to_string(&function, &RenderOptions::default());
+        assert!(out.contains(&format!("\n\"{}\"[fillcolor=", keyword)), "{}", out);
+        assert!(out.contains(&format!("\na -> \"{}\"\n", keyword)), "{}", out);
+        assert!(out.contains(&format!("\n\"{}\" -> a\n", keyword)), "{}", out);
+    }
+}
+
+#[test]
+fn node_to_string() {
+    let mut n = Node::new("foo");
+    let n = n
+        .shape(Shape::Ellipse)
+        .style(Style::Filled)
+        .fillcolor(&ColorScheme::Dark28.color(0));
+    assert_eq!(
+        n.to_string(),
+        "foo[fillcolor=\"/dark28/1\",shape=ellipse,style=filled];"
+    );
+
+    assert_eq!(Node::new("bar").to_string(), "bar;");
+}
+
+/// The functions of an owner, and the owners nested within it. The owner
+/// "a/b" is the child "b" of the child "a" of the root.
+#[derive(Default)]
+struct OwnerCluster<'a> {
+    functions: Vec<&'a str>,
+    children: BTreeMap<&'a str, OwnerCluster<'a>>,
+}
+
+impl<'a> OwnerCluster<'a> {
+    fn add(&mut self, owner: &'a str, function: &'a str) {
+        let mut cluster = self;
+        for part in owner.split('/') {
+            cluster = cluster.children.entry(part).or_default();
+        }
+        cluster.functions.push(function);
+    }
+
+    /// Adds a cluster for each child, named by appending the child's name
+    /// to `prefix`, and containing the child's functions and clusters.
+    fn add_subgraphs(&self, g: &mut DotBuilder, prefix: &str) {
+        for (name, child) in &self.children {
+            let id = format!("{}_{}", prefix, name);
+            g.add(format!("subgraph {} {{", dot_id(&id)));
+            g.add(format!("label={}", quote(name)));
+            for function in &child.functions {
+                g.add(dot_id(function));
+            }
+            child.add_subgraphs(g, &id);
+            g.add("}");
+        }
+    }
+}
+
+/// Assigns a colour from `palette` to the owner of each enabled function
+/// of `graph`. Disabled functions are drawn in grey, so don't take colours.
+fn owner_colours(graph: &Graph, palette: &Palette) -> BTreeMap<String, String> {
+    let mut owners: Vec<&str> = graph
+        .functions
+        .iter()
+        .filter(|f| f.is_enabled())
+        .map(|f| f.owner.as_str())
+        .collect();
+
+    owners.sort();
+    owners.dedup();
+
+    // Owners without a colour in the spec's legend take the next colour
+    // from the palette that isn't pinned to another owner, unless every
+    // colour is pinned.
+    let pinned: HashSet<&str> = graph.legend.values().map(String::as_str).collect();
+    let mut next = 0;
+    owners
+        .iter()
+        .map(|owner| {
+            if let Some(color) = graph.legend.get(*owner) {
+                return (owner.to_string(), color.clone());
+            }
+            let skip = (0..palette.len())
+                .find(|i| !pinned.contains(palette.color(next + i).as_str()))
+                .unwrap_or(0);
+            next += skip + 1;
+            (owner.to_string(), palette.color(next - 1))
+        })
+        .collect()
+}
+
+/// Assigns a colour from `palette` to each source of data in `graph`, in
+/// alphabetical order. Data with an empty source have no colour.
+fn source_colours(graph: &Graph, palette: &Palette) -> BTreeMap<String, String> {
+    let sources: BTreeSet<&str> = graph
+        .data
+        .iter()
+        .map(|d| d.source.as_str())
+        .filter(|s| !s.is_empty())
+        .collect();
+    sources
+        .iter()
+        .enumerate()
+        .map(|(i, source)| (source.to_string(), palette.color(i)))
+        .collect()
+}
+
+/// Renders `graph` as DOT, colouring owners from `opts.palette`.
+#[cfg(test)]
+fn render<W: std::io::Write>(
+    w: &mut W,
+    graph: &Graph,
+    opts: &RenderOptions
+) -> std::io::Result<()> {
+    render_coloured(w, graph, opts, &owner_colours(graph, &opts.palette))
+}
+
+/// Renders `graph` as DOT, with functions coloured by owner as given by
+/// `colours`, which must have an entry for the owner of every enabled
+/// function.
+fn render_coloured<W: std::io::Write>(
+    w: &mut W,
+    graph: &Graph,
+    opts: &RenderOptions,
+    colours: &BTreeMap<String, String>
+) -> std::io::Result<()> {
+    // See https://www.graphviz.org/doc/info/colors.html for the definitions
+    // of the colour schemes. Functions are colored according to their owner.
+    const DISABLED_COLOUR: &str = "grey";
+    const DISABLED_FILL_COLOUR: &str = "grey90";
+    // Nodes and edges outside the highlighted owner's footprint are drawn
+    // as if disabled.
+    const DIM_COLOUR: &str = DISABLED_COLOUR;
+    const DIM_FILL_COLOUR: &str = DISABLED_FILL_COLOUR;
+    const HIGHLIGHT_PENWIDTH: &str = "3";
+    const HEATMAP_NEUTRAL_COLOUR: &str = "white";
+    // Font sizes for --scale-by-degree, in points. Nodes with few flows
+    // are given the minimum, which is below Graphviz's default of 14.
+    const FONT_SIZE_PER_FLOW: usize = 4;
+    const MIN_FONT_SIZE: usize = 10;
+    const MAX_FONT_SIZE: usize = 28;
+
+    let mut functions: Vec<&Function> = graph
+        .functions
+        .iter()
+        .filter(|f| f.is_enabled() || opts.show_disabled)
+        .collect();
+    if opts.annotate_owners {
+        functions.sort_by(|a, b| a.owner.cmp(&b.owner));
+    }
+
+    // The names of the functions drawn by each function node, which are
+    // only different from the node's own name when merging duplicates.
+    let mut names: HashMap<&str, Vec<&str>> = HashMap::new();
+    if opts.merge_duplicates {
+        let mut first = HashMap::new();
+        functions.retain(|f| {
+            let mut inputs: Vec<&Flow> = f.inputs.iter().collect();
+            let mut outputs: Vec<&Flow> = f.outputs.iter().collect();
+            inputs.sort();
+            outputs.sort();
+            let key = (f.owner.as_str(), f.is_enabled(), inputs, outputs);
+            let representative = *first.entry(key).or_insert(f.name.as_str());
+            names.entry(representative).or_default().push(&f.name);
+            representative == f.name
+        });
+    }
+
+    let external: HashSet<String> = if opts.include_external {
+        analysis::external_inputs(graph).into_iter().collect()
+    } else {
+        HashSet::new()
+    };
+
+    // Whether each function is drawn as part of the highlighted owner's
+    // footprint, along with the data it reads or writes, if there is one.
+    let highlighted = |f: &Function| opts.highlight_owner.as_ref().map(|owner| &f.owner == owner);
+    let footprint: Option<HashSet<&str>> = opts.highlight_owner.as_ref().map(|_| {
+        functions
+            .iter()
+            .filter(|f| highlighted(f) == Some(true))
+            .flat_map(|f| f.inputs.iter().chain(&f.outputs))
+            .map(|flow| flow.name.as_str())
+            .collect()
+    });
+
+    // The range of the metrics of the nodes filled by --heatmap, which
+    // leaves out functions drawn as disabled or dimmed. NaN and infinite
+    // metrics are left out too, and drawn as if missing.
+    let heat_coloured = |f: &Function| f.is_enabled() && highlighted(f) != Some(false);
+    let metrics = graph.data
+        .iter()
+        .map(|d| d.metric)
+        .chain(functions.iter().filter(|f| heat_coloured(f)).map(|f| f.metric))
+        .flatten()
+        .filter(|m| m.is_finite());
+    let (min_metric, max_metric) = metrics.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), m| {
+        (min.min(m), max.max(m))
+    });
+    let heat = |metric: Option<f64>| match metric {
+        Some(metric) if metric.is_finite() => {
+            (heat_colour(metric, min_metric, max_metric), format!("metric={} -> fill", metric))
+        }
+        Some(metric) => (HEATMAP_NEUTRAL_COLOUR.to_string(), format!("metric={} -> fill", metric)),
+        None => (HEATMAP_NEUTRAL_COLOUR.to_string(), "no metric -> fill".to_string()),
+    };
+
+    let degrees = analysis::degrees(&graph.data, &functions);
+    let font_size = |name: &str| -> (usize, String) {
+        let degree = degrees.get(name).cloned().unwrap_or(0);
+        let size = (degree * FONT_SIZE_PER_FLOW).clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+        (size, format!("degree={} -> fontsize {}", degree, size))
+    };
+
+    // The reasons for how each node is drawn, for --explain, recorded as
+    // each decision is made.
+    let mut explanations = Vec::new();
+
+    let data_nodes: Vec<Node> = graph
+        .data
+        .iter()
+        .map(|d| {
+            let mut node = Node::new(opts.id(&d.name));
+            let shape = opts.data_shape.unwrap_or(Shape::Box);
+            node.shape(shape);
+            let mut reasons = vec![format!("shape={}", shape)];
+            if external.contains(&d.name) {
+                node.style(Style::Dashed);
+                reasons.push("external input -> dashed".to_string());
+            }
+            if opts.heatmap {
+                let (colour, reason) = heat(d.metric);
+                node.style(Style::Filled).attribute("fillcolor", &colour);
+                reasons.push(format!("{} {}", reason, colour));
+            } else if let Some(freshness) = d.freshness {
+                node.style(Style::Filled).fillcolor(freshness.fill_colour());
+                reasons.push(format!(
+                    "freshness={} -> fill {}",
+                    format!("{:?}", freshness).to_lowercase(),
+                    freshness.fill_colour()
+                ));
+            }
+            if opts.scale_by_degree {
+                let (size, reason) = font_size(&d.name);
+                node.attribute("fontsize", size.to_string());
+                reasons.push(reason);
+            }
+            if footprint.as_ref().is_some_and(|footprint| !footprint.contains(d.name.as_str())) {
+                node.attribute("color", DIM_COLOUR).attribute("fontcolor", DIM_COLOUR);
+                reasons.push("not used by highlighted owner -> dimmed".to_string());
+            }
+            explanations.push(format!("data {:?}: {}", d.name, reasons.join("; ")));
+            let text = if opts.show_data_source && !d.source.is_empty() {
+                format!("{}\\n({})", escape(&d.name), escape(&d.source))
+            } else {
+                escape(&d.name)
+            };
+            if shape == Shape::Record {
+                let ports = functions
+                    .iter()
+                    .flat_map(|f| f.inputs.iter().chain(&f.outputs))
+                    .filter(|flow| flow.name == d.name)
+                    .filter_map(|flow| flow.port.as_deref());
+                node.raw_attribute("label", record_label(&text, ports));
+            } else if (opts.show_data_source && !d.source.is_empty()) || !opts.ids.is_empty() {
+                node.raw_attribute("label", format!("\"{}\"", text));
+            }
+            if let Some(url) = &d.url {
+                node.url(url);
+            }
+            if let Some(pos) = &d.pos {
+                node.attribute("pos", pos);
+            }
+            if let Some(&change) = opts.overlay.nodes.get(&d.name) {
+                node.change(change);
+            }
+            if opts.interactive {
+                node.attribute("class", "data");
+            }
+            if let Some(description) = &d.description {
+                node.attribute("comment", description);
+            }
+            node.extra_attributes(&d.attributes);
+            node
+        })
+        .collect();
+
+    let function_nodes: Vec<Node> = functions
+        .iter()
+        .map(|f| {
+            let mut node = Node::new(opts.function_id(f));
+            let shape = if opts.ports { Shape::Record } else { opts.function_shape.unwrap_or(Shape::Ellipse) };
+            node.shape(shape).style(Style::Filled);
+            let merged = names.get(f.name.as_str()).filter(|names| names.len() > 1);
+            let mut text = match merged {
+                Some(names) => escape(&names.join(", ")),
+                None => escape(&f.name)
+            };
+            if opts.show_degree {
+                text = format!("{} ({}→{})", text, f.inputs.len(), f.outputs.len());
+            }
+            let number = opts.function_numbers.get(&f.name);
+            if let Some(number) = number {
+                text = format!("[{}] {}", number, text);
+            }
+            if opts.ports {
+                node.raw_attribute("label", ports_label(&text, f));
+            } else if shape == Shape::Record {
+                let ports = f.inputs.iter().chain(&f.outputs).filter_map(|flow| flow.function_port.as_deref());
+                node.raw_attribute("label", record_label(&text, ports));
+            } else if opts.show_degree || merged.is_some() || number.is_some() || !opts.ids.is_empty() {
+                node.raw_attribute("label", format!("\"{}\"", text));
+            }
+            let mut reasons = Vec::new();
+            if f.is_enabled() {
+                node.fillcolor(&colours[&f.owner]);
+                reasons.push(format!("owner={} -> color {}", f.owner, colours[&f.owner]));
+            } else {
+                node.attribute("fillcolor", DISABLED_FILL_COLOUR)
+                    .attribute("color", DISABLED_COLOUR)
+                    .attribute("fontcolor", DISABLED_COLOUR);
+                reasons.push(format!("owner={}, disabled -> color {}", f.owner, DISABLED_COLOUR));
+            }
+            if opts.heatmap && heat_coloured(f) {
+                let (colour, reason) = heat(f.metric);
+                node.attribute("fillcolor", &colour);
+                reasons.push(format!("{} {}", reason, colour));
+            }
+            match highlighted(f) {
+                Some(true) => {
+                    node.style(Style::Bold).attribute("penwidth", HIGHLIGHT_PENWIDTH);
+                    reasons.push("highlighted owner -> bold".to_string());
+                }
+                Some(false) => {
+                    node.attribute("fillcolor", DIM_FILL_COLOUR)
+                        .attribute("color", DIM_COLOUR)
+                        .attribute("fontcolor", DIM_COLOUR);
+                    reasons.push("not highlighted -> dimmed".to_string());
+                }
+                None => {}
+            }
+            reasons.push(format!("shape={}", shape));
+            if opts.scale_by_degree {
+                let (size, reason) = font_size(&f.name);
+                node.attribute("fontsize", size.to_string());
+                reasons.push(reason);
+            }
+            if opts.ports {
+                reasons.push(format!("ports for {} inputs and {} outputs", f.inputs.len(), f.outputs.len()));
+            }
+            if let Some(names) = merged {
+                reasons.push(format!("merged duplicates {}", names.join(", ")));
+            }
+            if let Some(number) = number {
+                reasons.push(format!("topological position {}", number));
+            }
+            if opts.cluster_owners {
+                reasons.push(format!("cluster={}", f.owner));
+            }
+            if opts.swimlanes {
+                reasons.push(format!("lane={}", f.owner));
+            }
+            explanations.push(format!("function {:?}: {}", f.name, reasons.join("; ")));
+            if let Some(url) = &f.url {
+                node.url(url);
+            }
+            if let Some(pos) = &f.pos {
+                node.attribute("pos", pos);
+            }
+            if let Some(&change) = opts.overlay.nodes.get(&f.name) {
+                node.change(change);
+            }
+            if opts.interactive {
+                node.attribute("class", format!("function {}", owner_class(&f.owner)));
+            }
+            if let Some(description) = &f.description {
+                node.attribute("comment", description);
+            }
+            node.extra_attributes(&f.attributes);
+            node
+        })
+        .collect();
+
+    let mut g = DotBuilder::new(opts.compact);
+    if !opts.fragment {
+        g.add(format!("digraph {} {{", graph_name(opts.graph_name.as_deref().unwrap_or("G"))));
+        if let Some(layout) = opts.layout {
+            g.add(format!("layout={}", layout));
+            if let (true, Some(seed)) = (layout.is_randomised(), opts.layout_seed) {
+                g.add(format!("start={}", seed));
+            }
+        }
+        if let Some(splines) = opts.splines {
+            g.add(format!("splines={}", splines));
+        }
+        if opts.legend_position == Some(LegendPosition::Right) {
+            // Allows the legend to share a rank with nodes outside its cluster.
+            g.add("newrank=true");
+        }
+    }
+    g.add_node_group(&data_nodes);
+
+    let owner_comment = |f: &Function| format!("// owner: {}", f.owner.replace('\n', " "));
+    if opts.compact && opts.annotate_owners {
+        let mut start = 0;
+        while start < functions.len() {
+            let owner = &functions[start].owner;
+            let len = functions[start..].iter().take_while(|f| &f.owner == owner).count();
+            g.add(owner_comment(functions[start]));
+            g.add_node_group(&function_nodes[start..start + len]);
+            start += len;
+        }
+    } else if opts.compact {
+        g.add_node_group(&function_nodes);
+    }
+    let mut grouped_edges = Vec::new();
+    // The colour of the edges of each data, when colouring them by source.
+    let sources = if opts.color_edges_by_source {
+        source_colours(graph, &opts.palette)
+    } else {
+        BTreeMap::new()
+    };
+    let edge_colours: HashMap<&str, &str> = graph
+        .data
+        .iter()
+        .filter_map(|d| sources.get(&d.source).map(|c| (d.name.as_str(), c.as_str())))
+        .collect();
+    for (i, (f, node)) in functions.iter().zip(&function_nodes).enumerate() {
+        if !opts.compact {
+            if opts.annotate_owners && (i == 0 || functions[i - 1].owner != f.owner) {
+                g.add(owner_comment(f));
+            }
+            g.add_node(node);
+        }
+        let function_port = |flow: &Flow, input: bool, n: usize| {
+            if opts.ports {
+                Some(flow_port(input, n))
+            } else {
+                flow.function_port.clone()
+            }
+        };
+        let edges = f.inputs
+            .iter()
+            .enumerate()
+            .map(|(n, i)| {
+                let mut edge = Edge::new(opts.id(&i.name), opts.function_id(f));
+                edge.ports(i.port.as_deref(), function_port(i, true, n).as_deref());
+                (i, edge)
+            })
+            .chain(f.outputs.iter().enumerate().map(|(n, o)| {
+                let mut edge = Edge::new(opts.function_id(f), opts.id(&o.name));
+                edge.ports(function_port(o, false, n).as_deref(), o.port.as_deref());
+                (o, edge)
+            }));
+        for (flow, mut edge) in edges {
+            if let Some(arrowhead) = flow.kind.as_ref().and_then(|k| opts.arrowhead(k)) {
+                edge.attribute("arrowhead", arrowhead);
+            }
+            let labels: Vec<&str> = flow.latency.iter().chain(&flow.format).map(String::as_str).collect();
+            if !labels.is_empty() {
+                edge.attribute("label", labels.join(", "));
+            }
+            if let Some(weight) = flow.weight {
+                edge.attribute("weight", weight.to_string());
+            }
+            if let Some(note) = &flow.note {
+                edge.attribute("edgetooltip", note);
+            }
+            if let Some(colour) = edge_colours.get(flow.name.as_str()) {
+                edge.attribute("color", colour);
+            }
+            if !f.is_enabled() {
+                edge.attribute("color", DISABLED_COLOUR);
+            } else if highlighted(f) == Some(false) {
+                edge.attribute("color", DIM_COLOUR);
+            }
+            match opts.overlay.edges.get(&(edge.from.clone(), edge.to.clone())) {
+                Some(diff::Change::Added) => {
+                    edge.attribute("color", "green");
+                }
+                Some(diff::Change::Removed) => {
+                    edge.attribute("color", "red").attribute("style", "dashed");
+                }
+                _ => {}
+            }
+            if opts.interactive {
+                edge.attribute("class", owner_class(&f.owner));
+            }
+            if opts.group_edges {
+                grouped_edges.push(edge);
+            } else {
+                g.add_edge(&edge);
+            }
+        }
+    }
+    grouped_edges.sort_by(|a, b| {
+        (&a.from, &a.from_port, &a.to, &a.to_port).cmp(&(&b.from, &b.from_port, &b.to, &b.to_port))
+    });
+    for edge in &grouped_edges {
+        g.add_edge(edge);
+    }
+
+    // The ids of data and function nodes, which nodes added by flux itself
+    // must avoid.
+    let node_ids: HashSet<&str> = graph.data
+        .iter()
+        .map(|d| opts.id(&d.name))
+        .chain(graph.functions.iter().map(|f| opts.function_id(f)))
+        .collect();
+
+    let drawn: HashSet<&str> = graph.data
+        .iter()
+        .map(|d| d.name.as_str())
+        .chain(functions.iter().map(|f| f.name.as_str()))
+        .collect();
+    for (i, note) in graph.notes.iter().enumerate() {
+        if note.near.as_ref().is_some_and(|near| !drawn.contains(near.as_str())) {
+            continue;
+        }
+        let name = unused_id(format!("note_{}", i + 1), &node_ids);
+        g.add_node(Node::new(&name).shape(Shape::Note).attribute("label", &note.text));
+        if let Some(near) = &note.near {
+            for id in opts.ids_named(near, &functions) {
+                g.add_edge(Edge::new(&name, id).attribute("style", "dashed").attribute("arrowhead", "none"));
+            }
+        }
+    }
+
+    if opts.with_catalog && !graph.data.is_empty() {
+        let catalog = unused_id("catalog".to_string(), &node_ids);
+        g.add_node(Node::new(&catalog)
+            .shape(Shape::Plaintext)
+            .raw_attribute("label", catalog_label(&graph.data))
+        );
+        g.add(format!("{{rank=sink;{}}}", dot_id(&catalog)));
+    }
+
+    if opts.cluster_owners {
+        let mut root = OwnerCluster::default();
+        for f in &functions {
+            root.add(&f.owner, opts.function_id(f));
+        }
+        root.add_subgraphs(&mut g, "cluster");
+    }
+
+    if opts.label_components {
+        let components = analysis::components(&graph.data, &functions);
+        if components.len() > 1 {
+            for (i, names) in components.iter().enumerate() {
+                g.add(format!("subgraph cluster_component_{} {{", i + 1));
+                g.add(format!("label=\"Component {}\"", i + 1));
+                for id in names.iter().flat_map(|name| opts.ids_named(name, &functions)) {
+                    g.add(dot_id(id));
+                }
+                g.add("}");
+            }
+        }
+    }
+
+    if opts.swimlanes {
+        // Each owner's functions share a rank, along with an anchor node
+        // which labels the lane. Invisible edges between the anchors keep
+        // the lanes in order.
+        let mut lanes: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for f in &functions {
+            lanes.entry(&f.owner).or_default().push(opts.function_id(f));
+        }
+        // Each lane's subgraph is named after its anchor.
+        let anchor = |owner: &str| unused_id(format!("lane_{}", owner), &node_ids);
+        for (owner, names) in &lanes {
+            g.add(format!("subgraph {} {{", dot_id(&anchor(owner))));
+            g.add("rank=same");
+            g.add_node(Node::new(&anchor(owner))
+                .attribute("label", owner)
+                .shape(Shape::Plaintext)
+            );
+            for name in names {
+                g.add(dot_id(name));
+            }
+            g.add("}");
+        }
+        if lanes.len() > 1 {
+            let anchors: Vec<String> = lanes.keys().map(|o| dot_id(&anchor(o))).collect();
+            let arrow = if opts.compact { "->" } else { " -> " };
+            g.add(format!("{}[style=invis]", anchors.join(arrow)));
+        }
+    }
+
+    if opts.overview {
+        let owners: BTreeSet<&str> = functions
+            .iter()
+            .filter(|f| f.is_enabled())
+            .map(|f| f.owner.as_str())
+            .collect();
+        let overview_id = |owner: &str| unused_id(format!("overview_{}", owner), &node_ids);
+        g.add("subgraph cluster_overview {");
+        g.add("label=\"Overview\"");
+        let nodes: Vec<Node> = owners
+            .iter()
+            .map(|owner| {
+                let mut node = Node::new(&overview_id(owner));
+                node.attribute("label", owner)
+                    .shape(Shape::Box)
+                    .style(Style::Filled)
+                    .fillcolor(&colours[*owner]);
+                node
+            })
+            .collect();
+        g.add_node_group(&nodes);
+        for (from, to) in analysis::owner_dependencies(graph) {
+            if owners.contains(from) && owners.contains(to) {
+                g.add_edge(&Edge::new(&overview_id(from), &overview_id(to)));
+            }
+        }
+        g.add("}");
+    }
+
+    // A fragment is embedded in another graph, which has its own legend.
+    if !opts.fragment {
+        g.add("subgraph cluster_legend {");
+        g.add(format!("label={}", quote(opts.legend_title.as_deref().unwrap_or("Legend"))));
+        if !opts.compact {
+            // rankdir has no effect on subgraphs, so is omitted when compacting.
+            g.add("rankdir=TB");
+        }
+        // Only owners drawn in this graph appear in the legend, as `colours`
+        // may cover a larger graph that this is part of.
+        let drawn_owners: HashSet<&str> = functions
+            .iter()
+            .filter(|f| f.is_enabled())
+            .map(|f| f.owner.as_str())
+            .collect();
+        let legend_nodes: Vec<Node> = colours
+            .iter()
+            .filter(|(name, _)| drawn_owners.contains(name.as_str()))
+            .map(|(name, color)| {
+                let mut node = Node::new(&unused_id(format!("legend_{}", name), &node_ids));
+                node.style(Style::Filled).fillcolor(color);
+                if opts.legend_initials {
+                    node.attribute("label", initials(name)).attribute("tooltip", name);
+                } else {
+                    node.attribute("label", name);
+                }
+                if opts.interactive {
+                    node.attribute("id", format!("legend_{}", owner_class(name)))
+                        .attribute("class", format!("legend {}", owner_class(name)));
+                }
+                node
+            })
+            .collect();
+        g.add_node_group(&legend_nodes);
+        // Invisible edges keep the legend entries in a single column. There's
+        // nothing to order with fewer than two entries, and a lone node with
+        // style=invis would hide that entry.
+        if legend_nodes.len() > 1 {
+            let names: Vec<String> = legend_nodes.iter().map(|n| dot_id(&n.name)).collect();
+            let arrow = if opts.compact { "->" } else { " -> " };
+            g.add(format!("{}[style=invis]", names.join(arrow)));
+        }
+        g.add("}");
+
+        if let (Some(position), Some(first), Some(last)) =
+            (opts.legend_position, legend_nodes.first(), legend_nodes.last()) {
+            // Invisible edges from or to the legend move it above the graph's
+            // roots, below its sinks, or alongside its first root.
+            let mut has_inputs = HashSet::new();
+            let mut has_outputs = HashSet::new();
+            for f in &functions {
+                for i in &f.inputs {
+                    has_outputs.insert(i.name.as_str());
+                    has_inputs.insert(f.name.as_str());
+                }
+                for o in &f.outputs {
+                    has_outputs.insert(f.name.as_str());
+                    has_inputs.insert(o.name.as_str());
+                }
+            }
+            let nodes = graph.data
+                .iter()
+                .map(|d| (d.name.as_str(), opts.id(&d.name)))
+                .chain(functions.iter().map(|f| (f.name.as_str(), opts.function_id(f))));
+            let (first, last) = (dot_id(&first.name), dot_id(&last.name));
+            let arrow = if opts.compact { "->" } else { " -> " };
+            match position {
+                LegendPosition::Top => {
+                    for (_, root) in nodes.filter(|(n, _)| !has_inputs.contains(n)) {
+                        g.add(format!("{}{}{}[style=invis]", last, arrow, dot_id(root)));
+                    }
+                }
+                LegendPosition::Bottom => {
+                    for (_, sink) in nodes.filter(|(n, _)| !has_outputs.contains(n)) {
+                        g.add(format!("{}{}{}[style=invis]", dot_id(sink), arrow, first));
+                    }
+                }
+                LegendPosition::Right => {
+                    if let Some((_, root)) = nodes.clone().find(|(n, _)| !has_inputs.contains(n)) {
+                        let root = dot_id(root);
+                        g.add(format!("{{rank=same;{};{}}}", root, first));
+                        g.add(format!("{}{}{}[style=invis]", root, arrow, first));
+                    }
+                }
+            }
+        }
+        g.add("}");
+    }
+
+    g.render(w)?;
+
+    if opts.explain {
+        for explanation in &explanations {
+            eprintln!("{}", explanation);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn font_size_scaled_by_degree() {
+    let graph = parse_graph(r#"
+data:
+  - {name: events, source: s}
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: fanout, owner: o, inputs: [events], outputs: [a, b, c]}
+  - {name: read, owner: o, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let (nodes, _) = summarise_dot(&render_to_string(&graph, &RenderOptions::default()));
+    assert!(!nodes["fanout"].contains_key("fontsize"));
+
+    let out = render_to_string(&graph, &RenderOptions { scale_by_degree: true, ..Default::default() });
+    let (nodes, _) = summarise_dot(&out);
+    let size = |name: &str| nodes[name]["fontsize"].parse::<usize>().unwrap();
+    assert!(size("fanout") > size("read"));
+    assert!(size("fanout") > size("events"));
+    assert_eq!(size("events"), 10);
+}
+
+#[test]
+fn heatmap_fills_by_metric() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s, metric: 10}
+  - {name: b, source: s}
+functions:
+  - {name: cheap, owner: o, inputs: [a], outputs: [b], metric: 1.5}
+  - {name: middling, owner: o, inputs: [a], outputs: [b], metric: 4}
+  - {name: costly, owner: o, inputs: [b], outputs: [], metric: 40}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { heatmap: true, ..Default::default() });
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["cheap"]["fillcolor"], "\"0.333 0.700 0.900\"");
+    assert_eq!(nodes["costly"]["fillcolor"], "\"0.000 0.700 0.900\"");
+    assert_ne!(nodes["middling"]["fillcolor"], nodes["cheap"]["fillcolor"]);
+    assert_eq!(nodes["b"]["fillcolor"], "white");
+    assert_eq!(nodes["b"]["style"], "filled");
+}
+
+#[test]
+fn heatmap_range_ignores_disabled_and_dimmed_functions() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: cheap, owner: o, inputs: [a], outputs: [], metric: 1}
+  - {name: costly, owner: o, inputs: [a], outputs: [], metric: 3}
+  - {name: retired, owner: o, inputs: [a], outputs: [], metric: 1000, enabled: false}
+  - {name: other, owner: p, inputs: [a], outputs: [], metric: -1000}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions {
+        heatmap: true,
+        show_disabled: true,
+        highlight_owner: Some("o".to_string()),
+        ..Default::default()
+    });
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["cheap"]["fillcolor"], "\"0.333 0.700 0.900\"");
+    assert_eq!(nodes["costly"]["fillcolor"], "\"0.000 0.700 0.900\"");
+    assert_eq!(nodes["retired"]["fillcolor"], "grey90");
+    assert_eq!(nodes["other"]["fillcolor"], "grey90");
+}
+
+#[test]
+fn heatmap_ignores_non_finite_metrics() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s, metric: .nan}
+  - {name: b, source: s, metric: .inf}
+  - {name: c, source: s, metric: -.inf}
+functions:
+  - {name: cheap, owner: o, inputs: [a], outputs: [b], metric: 1}
+  - {name: costly, owner: o, inputs: [b], outputs: [c], metric: 3}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { heatmap: true, ..Default::default() });
+    let (nodes, _) = summarise_dot(&out);
+    for name in &["a", "b", "c"] {
+        assert_eq!(nodes[*name]["fillcolor"], "white");
+    }
+    assert_eq!(nodes["cheap"]["fillcolor"], "\"0.333 0.700 0.900\"");
+    assert_eq!(nodes["costly"]["fillcolor"], "\"0.000 0.700 0.900\"");
+}
+
+#[test]
+fn data_filled_by_freshness() {
+    let graph = parse_graph(r#"
+data:
+  - {name: live, source: s, freshness: realtime}
+  - {name: batch, source: s, freshness: daily}
+  - {name: old, source: s, freshness: stale}
+  - {name: plain, source: s}
+functions:
+  - {name: f, owner: o, inputs: [live, batch, old], outputs: [plain]}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { include_external: true, ..Default::default() });
+    let (nodes, _) = summarise_dot(&out);
+    let fills: HashSet<&str> = ["live", "batch", "old"].iter().map(|n| nodes[*n]["fillcolor"].as_str()).collect();
+    assert_eq!(fills.len(), 3);
+    assert_eq!(nodes["old"]["style"], "\"dashed,filled\"");
+    assert!(!nodes["plain"].contains_key("fillcolor"));
+    assert!(!nodes["plain"].contains_key("style"));
+
+    assert!(parse_graph("data: [{name: a, source: s, freshness: weekly}]\nfunctions: []").is_err());
+}
+
+#[test]
+fn legend_positions() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+  - {name: g, owner: p, inputs: [a], outputs: []}
+"#).unwrap();
+    let render = |position| render_to_string(&graph, &RenderOptions {
+        legend_position: position,
+        ..Default::default()
+    });
+
+    let free = render(None);
+    assert!(!free.contains("newrank"));
+
+    let top = render(Some(LegendPosition::Top));
+    assert!(top.ends_with("}\nlegend_p -> a[style=invis]\n}\n"));
+
+    let bottom = render(Some(LegendPosition::Bottom));
+    assert!(bottom.contains("\nb -> legend_o[style=invis]\ng -> legend_o[style=invis]\n}\n"));
+
+    let right = render(Some(LegendPosition::Right));
+    assert!(right.contains("\nnewrank=true\n"));
+    assert!(right.contains("\n{rank=same;a;legend_o}\na -> legend_o[style=invis]\n}\n"));
+
+    assert!("left".parse::<LegendPosition>().is_err());
+}
+
+#[test]
+fn url_only_on_nodes_that_declare_one() {
+    let graph: Graph = serde_yaml::from_str(r#"
+data:
+  - name: Events
+    source: Player
+    url: https://example.com/events?a="b"
+  - name: Position
+    source: GameLoop
+functions:
+  - name: Update
+    owner: Game
+    url: https://example.com/runbook
+    inputs: [Events]
+    outputs: [Position]
+  - name: Draw
+    owner: Game
+    inputs: [Position]
+    outputs: []
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    let line = |name: &str| {
+        out.lines()
+            .find(|l| l.starts_with(&format!("{}[", name)))
+            .unwrap()
+            .to_string()
+    };
+
+    assert!(line("Events").contains(r#"URL="https://example.com/events?a=\"b\"""#));
+    assert!(line("Update").contains(r#"URL="https://example.com/runbook""#));
+    assert!(!line("Position").contains("URL"));
+    assert!(!line("Draw").contains("URL"));
+}
+
+#[test]
+fn layout_attribute_emitted() {
+    let graph = parse_graph(include_str!("sample.yaml")).unwrap();
+
+    let default = render_to_string(&graph, &RenderOptions::default());
+    assert!(!default.contains("layout="));
+
+    let layout: Layout = "neato".parse().unwrap();
+    let out = render_to_string(&graph, &RenderOptions {
+        layout: Some(layout),
+        ..Default::default()
+    });
+    assert_eq!(out.lines().nth(1), Some("layout=neato"));
+
+    assert!("graphviz".parse::<Layout>().is_err());
+}
+
+#[test]
+fn splines_attribute_emitted() {
+    let graph = parse_graph(include_str!("sample.yaml")).unwrap();
+
+    let default = render_to_string(&graph, &RenderOptions::default());
+    assert!(!default.contains("splines="));
+
+    let out = render_to_string(&graph, &RenderOptions {
+        splines: Some("ortho".parse().unwrap()),
+        ..Default::default()
+    });
+    assert_eq!(out.lines().nth(1), Some("splines=ortho"));
+    assert_eq!(out.matches("splines=").count(), 1);
+
+    let err = "wavy".parse::<Splines>().unwrap_err();
+    assert_eq!(err, "unknown splines 'wavy', expected one of ortho, polyline, curved, line");
+}
+
+#[test]
+fn layout_seed_emitted_for_randomised_layouts() {
+    let graph = parse_graph(include_str!("sample.yaml")).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions {
+        layout: Some(Layout::Fdp),
+        layout_seed: Some(42),
+        ..Default::default()
+    });
+    assert_eq!(out.lines().nth(2), Some("start=42"));
+
+    for layout in &[None, Some(Layout::Dot), Some(Layout::Circo)] {
+        let out = render_to_string(&graph, &RenderOptions {
+            layout: *layout,
+            layout_seed: Some(42),
+            ..Default::default()
+        });
+        assert!(!out.contains("start="), "{}", out);
+    }
+}
+
+#[test]
+fn disabled_functions() {
+    let graph = parse_graph(r#"
+data:
+  - name: Events
+    source: Player
+  - name: Position
+    source: GameLoop
+functions:
+  - name: Update
+    owner: Game
+    inputs: [Events]
+    outputs: [Position]
+  - name: Predict
+    owner: Ai
+    enabled: false
+    inputs: [Position]
+    outputs: [Events]
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert!(!out.contains("Predict"));
+    assert!(!out.contains("legend_Ai"));
+    assert!(out.contains("Events -> Update"));
+
+    let out = render_to_string(&graph, &RenderOptions {
+        show_disabled: true,
+        ..Default::default()
+    });
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes["Predict"]["fillcolor"], "grey90");
+    assert_eq!(nodes["Predict"]["fontcolor"], "grey");
+    assert!(edges.contains(&("Position".into(), "Predict".into(), r#"{"color": "grey"}"#.into())));
+    assert!(edges.contains(&("Update".into(), "Position".into(), "{}".into())));
+    assert!(!out.contains("legend_Ai"));
+}
+
+#[test]
+fn arrowheads_by_flow_kind() {
+    let graph = parse_graph(r#"
+data:
+  - name: Events
+    source: Player
+  - name: Position
+    source: GameLoop
+  - name: Log
+    source: GameLoop
+functions:
+  - name: Update
+    owner: Game
+    inputs: [Events]
+    outputs:
+      - {name: Position, kind: write}
+      - {name: Log, kind: event}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert!(out.contains("Update -> Position[arrowhead=diamond]"));
+    assert!(out.contains("Update -> Log[arrowhead=vee]"));
+    assert!(out.contains("Events -> Update\n"));
+
+    let mut opts = RenderOptions::default();
+    opts.arrowheads.insert("write".into(), "box".into());
+    let out = render_to_string(&graph, &opts);
+    assert!(out.contains("Update -> Position[arrowhead=box]"));
+    assert!(out.contains("Update -> Log[arrowhead=vee]"));
+}
+
+#[test]
+fn custom_legend_title() {
+    let graph = parse_graph(include_str!("sample.yaml")).unwrap();
+
+    let legend = |out: &str| {
+        let start = out.find("subgraph cluster_legend {").unwrap();
+        out[start..].lines().nth(1).unwrap().to_string()
+    };
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert_eq!(legend(&out), r#"label="Legend""#);
+
+    let out = render_to_string(&graph, &RenderOptions {
+        legend_title: Some(r#"Teams ("owners")"#.into()),
+        ..Default::default()
+    });
+    assert_eq!(legend(&out), r#"label="Teams (\"owners\")""#);
+}
+
+#[test]
+fn custom_palette_wraps() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: Alpha, inputs: [a], outputs: []}
+  - {name: g, owner: Beta, inputs: [a], outputs: []}
+  - {name: h, owner: Gamma, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let palette: Palette = "#1f77b4, red".parse().unwrap();
+    let out = render_to_string(&graph, &RenderOptions {
+        palette,
+        ..Default::default()
+    });
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["f"]["fillcolor"], r##""#1f77b4""##);
+    assert_eq!(nodes["g"]["fillcolor"], "red");
+    assert_eq!(nodes["h"]["fillcolor"], r##""#1f77b4""##);
+    assert_eq!(nodes["legend_Gamma"]["fillcolor"], r##""#1f77b4""##);
+
+    assert!("#12345".parse::<Palette>().is_err());
+    assert!("red,".parse::<Palette>().is_err());
+}
+
+#[test]
+fn legend_pins_owner_colours() {
+    let graph = parse_graph(r##"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: Billing, inputs: [a], outputs: []}
+  - {name: g, owner: Search, inputs: [a], outputs: []}
+legend:
+  Billing: "#1f77b4"
+"##).unwrap();
+
+    // Search would be assigned #1f77b4 as the first colour, but it's
+    // reserved for Billing.
+    let palette: Palette = "#1f77b4, red".parse().unwrap();
+    let out = render_to_string(&graph, &RenderOptions {
+        palette,
+        ..Default::default()
+    });
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["f"]["fillcolor"], r##""#1f77b4""##);
+    assert_eq!(nodes["g"]["fillcolor"], "red");
+    assert_eq!(nodes["legend_Billing"]["fillcolor"], r##""#1f77b4""##);
+    assert_eq!(nodes["legend_Search"]["fillcolor"], "red");
+
+    let err = parse_graph("data: []\nfunctions: []\nlegend: {Billing: \"not a colour\"}\n").unwrap_err();
+    assert!(err.to_string().contains("invalid legend colour for 'Billing'"), "{}", err);
+}
+
+#[test]
+fn data_source_in_label() {
+    let graph = parse_graph(r#"
+data:
+  - {name: Position, source: GameLoop}
+  - {name: Seed, source: ""}
+functions:
+  - {name: Update, owner: Game, inputs: [Seed], outputs: [Position]}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert!(!out.contains("label=\"Position"));
+
+    let out = render_to_string(&graph, &RenderOptions {
+        show_data_source: true,
+        ..Default::default()
+    });
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["Position"]["label"], r#""Position\n(GameLoop)""#);
+    assert!(!nodes["Seed"].contains_key("label"));
+}
+
+#[test]
+fn external_inputs_are_dashed() {
+    let graph = parse_graph(r#"
+data:
+  - {name: Clicks, source: Browser}
+  - {name: Sessions, source: Pipeline}
+functions:
+  - {name: Sessionise, owner: Pipeline, inputs: [Clicks], outputs: [Sessions]}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert!(!out.contains("dashed"));
+
+    let out = render_to_string(&graph, &RenderOptions {
+        include_external: true,
+        ..Default::default()
+    });
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["Clicks"]["style"], "dashed");
+    assert!(!nodes["Sessions"].contains_key("style"));
+}
+
+#[test]
+fn swimlanes_per_owner() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: f, owner: Ingest, inputs: [a], outputs: [b]}
+  - {name: g, owner: Serve, inputs: [b], outputs: [c]}
+  - {name: h, owner: Ingest, inputs: [c], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert!(!out.contains("rank=same"));
+
+    let out = render_to_string(&graph, &RenderOptions {
+        swimlanes: true,
+        ..Default::default()
+    });
+    assert!(out.contains("\
+subgraph lane_Ingest {
+rank=same
+lane_Ingest[label=Ingest,shape=plaintext];
+f
+h
+}
+subgraph lane_Serve {
+rank=same
+lane_Serve[label=Serve,shape=plaintext];
+g
+}
+lane_Ingest -> lane_Serve[style=invis]
+"));
+}
+
+#[test]
+fn lane_ids_avoid_node_names() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: lane_Billing, owner: Search, inputs: [a], outputs: []}
+  - {name: bill, owner: Billing, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { swimlanes: true, ..Default::default() });
+    assert!(out.contains("\
+subgraph lane_Billing_ {
+rank=same
+lane_Billing_[label=Billing,shape=plaintext];
+bill
+}
+subgraph lane_Search {
+rank=same
+lane_Search[label=Search,shape=plaintext];
+lane_Billing
+}
+lane_Billing_ -> lane_Search[style=invis]
+"), "{}", out);
+}
+
+#[test]
+fn node_positions() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s, pos: "10,20.5"}
+  - {name: b, source: s}
+functions:
+  - {name: f, owner: o, pos: "-3,4!", inputs: [a], outputs: [b]}
+"#).unwrap();
+
+    let (nodes, _) = summarise_dot(&render_to_string(&graph, &RenderOptions::default()));
+    assert_eq!(nodes["a"]["pos"], r#""10,20.5""#);
+    assert_eq!(nodes["f"]["pos"], r#""-3,4!""#);
+    assert!(!nodes["b"].contains_key("pos"));
+
+    for pos in &["10", "1,2,3", "x,1", ""] {
+        let spec = format!("{{data: [{{name: a, source: s, pos: '{}'}}], functions: []}}", pos);
+        let err = parse_graph(&spec).unwrap_err();
+        assert!(err.to_string().contains("invalid position"), "{}", err);
+    }
+}
+
+#[test]
+fn custom_node_shapes() {
+    let graph = parse_graph(include_str!("sample.yaml")).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions {
+        data_shape: Some("cylinder".parse().unwrap()),
+        function_shape: Some("diamond".parse().unwrap()),
+        ..Default::default()
+    });
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["Position"]["shape"], "cylinder");
+    assert_eq!(nodes["UpdatePosition"]["shape"], "diamond");
+
+    assert!("blob".parse::<Shape>().is_err());
+}
+
+#[test]
+fn function_degrees() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: enrich, owner: o, inputs: [a, b], outputs: [c]}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { show_degree: true, ..Default::default() });
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes["enrich"]["label"], "\"enrich (2→1)\"");
+    assert!(!nodes["a"].contains_key("label"));
+    assert!(edges.iter().any(|(from, to, _)| from == "a" && to == "enrich"));
+    assert!(edges.iter().any(|(from, to, _)| from == "enrich" && to == "c"));
+}
+
+#[test]
+fn functions_numbered_in_topological_order() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: summarise, owner: o, inputs: [b, c], outputs: []}
+  - {name: load, owner: o, inputs: [a], outputs: [b]}
+  - {name: enrich, owner: o, inputs: [a], outputs: [c]}
+"#).unwrap();
+
+    let function_numbers = analysis::topological_order(&graph)
+        .unwrap()
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| (name, i + 1))
+        .collect();
+    let out = render_to_string(&graph, &RenderOptions { function_numbers, ..Default::default() });
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes["enrich"]["label"], "\"[1] enrich\"");
+    assert_eq!(nodes["load"]["label"], "\"[2] load\"");
+    assert_eq!(nodes["summarise"]["label"], "\"[3] summarise\"");
+    assert!(!nodes["a"].contains_key("label"));
+    assert!(edges.iter().any(|(from, to, _)| from == "b" && to == "summarise"));
+    assert!(edges.iter().any(|(from, to, _)| from == "enrich" && to == "c"));
+}
+
+#[test]
+fn highlighted_owner_emphasised() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: bill, owner: Billing, inputs: [a], outputs: [b]}
+  - {name: search, owner: Search, inputs: [c], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions {
+        highlight_owner: Some("Billing".to_string()),
+        ..Default::default()
+    });
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes["bill"]["fillcolor"], "\"/dark28/1\"");
+    assert_eq!(nodes["bill"]["style"], "\"filled,bold\"");
+    assert_eq!(nodes["bill"]["penwidth"], "3");
+    assert!(!nodes["a"].contains_key("color"));
+
+    assert_eq!(nodes["search"]["fillcolor"], "grey90");
+    assert_eq!(nodes["search"]["color"], "grey");
+    assert_eq!(nodes["search"]["fontcolor"], "grey");
+    assert_eq!(nodes["c"]["color"], "grey");
+    assert!(edges.contains(&("a".into(), "bill".into(), "{}".into())));
+    assert!(edges.contains(&("c".into(), "search".into(), r#"{"color": "grey"}"#.into())));
+}
+
+#[test]
+fn overlay_styles_changes() {
+    let old = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+  - {name: g, owner: o, inputs: [b], outputs: []}
+"#).unwrap();
+    let new = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [c]}
+  - {name: h, owner: o, inputs: [c], outputs: []}
+"#).unwrap();
+
+    let (graph, overlay) = diff::overlay(&old, &new);
+    let out = render_to_string(&graph, &RenderOptions { overlay, ..Default::default() });
+    let (nodes, edges) = summarise_dot(&out);
+    assert!(!nodes["a"].contains_key("color"));
+    assert_eq!(nodes["c"]["color"], "green");
+    assert_eq!(nodes["h"]["color"], "green");
+    assert_eq!(nodes["b"]["color"], "red");
+    assert_eq!(nodes["b"]["style"], "dashed");
+    assert_eq!(nodes["g"]["color"], "red");
+    assert_eq!(nodes["g"]["style"], "\"filled,dashed\"");
+    assert_eq!(nodes["f"]["color"], "orange");
+    assert!(edges.contains(&("a".into(), "f".into(), "{}".into())));
+    assert!(edges.contains(&("f".into(), "c".into(), r#"{"color": "green"}"#.into())));
+    assert!(edges.contains(&("f".into(), "b".into(), r#"{"color": "red", "style": "dashed"}"#.into())));
+    assert!(edges.contains(&("b".into(), "g".into(), r#"{"color": "red", "style": "dashed"}"#.into())));
+}
+
+#[test]
+fn edges_coloured_by_source() {
+    let graph = parse_graph(r#"
+data:
+  - {name: clicks, source: Web}
+  - {name: orders, source: Shop}
+  - {name: rates, source: ""}
+  - {name: report, source: Shop}
+functions:
+  - {name: summarise, owner: o, inputs: [clicks, orders, rates], outputs: [report]}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { color_edges_by_source: true, ..Default::default() });
+    let (_, edges) = summarise_dot(&out);
+    assert!(edges.contains(&("orders".into(), "summarise".into(), r#"{"color": "\"/dark28/1\""}"#.into())));
+    assert!(edges.contains(&("summarise".into(), "report".into(), r#"{"color": "\"/dark28/1\""}"#.into())));
+    assert!(edges.contains(&("clicks".into(), "summarise".into(), r#"{"color": "\"/dark28/2\""}"#.into())));
+    assert!(edges.contains(&("rates".into(), "summarise".into(), "{}".into())));
+}
+
+#[test]
+fn fragment_omits_graph_and_legend() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions {
+        fragment: true,
+        layout: Some(Layout::Neato),
+        ..Default::default()
+    });
+    assert_eq!(out, "a[shape=box];\nf[fillcolor=\"/dark28/1\",shape=ellipse,style=filled];\na -> f\n");
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes.len(), 2);
+    assert_eq!(edges.len(), 1);
+}
+
+#[test]
+fn extra_node_attributes() {
+    let graph = parse_graph(r#"
+data:
+  - name: a
+    source: s
+    attributes: {fontsize: "20", shape: circle}
+functions:
+  - name: f
+    owner: o
+    inputs: [a]
+    outputs: []
+    attributes: {peripheries: "2"}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["a"]["fontsize"], "20");
+    assert_eq!(nodes["a"]["shape"], "box");
+    assert_eq!(nodes["f"]["peripheries"], "2");
+
+    let formatted = format::canonical_yaml(&graph).unwrap();
+    assert_eq!(parse_graph(&formatted).unwrap(), graph);
+}
+
+#[test]
+fn latency_labels_edges() {
+    let graph = parse_graph(r#"
+data:
+  - {name: events, source: s}
+  - {name: alerts, source: s}
+functions:
+  - name: f
+    owner: o
+    inputs: [{name: events, latency: "p99: \"50ms\""}]
+    outputs: [alerts]
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert!(out.contains(r#"events -> f[label="p99: \"50ms\""]"#), "{}", out);
+    assert!(out.contains("f -> alerts\n"));
+}
+
+#[test]
+fn formats_label_edges() {
+    let graph = parse_graph(r#"
+data:
+  - {name: orders, source: s}
+  - {name: invoices, source: s}
+  - {name: ledger, source: s}
+functions:
+  - name: bill
+    owner: o
+    inputs: [{name: orders, format: avro}]
+    outputs: [{name: invoices, format: json, latency: "p99: 50ms"}, ledger]
+"#).unwrap();
+
+    let (_, edges) = summarise_dot(&render_to_string(&graph, &RenderOptions::default()));
+    assert!(edges.contains(&("orders".into(), "bill".into(), r#"{"label": "avro"}"#.into())));
+    assert!(edges.contains(&("bill".into(), "invoices".into(), r#"{"label": "\"p99: 50ms, json\""}"#.into())));
+    assert!(edges.contains(&("bill".into(), "ledger".into(), "{}".into())));
+}
+
+#[test]
+fn notes_emitted_as_edge_tooltips() {
+    let graph = parse_graph(r#"
+data:
+  - {name: orders, source: s}
+  - {name: invoices, source: s}
+functions:
+  - name: bill
+    owner: o
+    inputs: [{name: orders, note: 'only "settled" orders'}]
+    outputs: [invoices]
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert!(out.contains(r#"orders -> bill[edgetooltip="only \"settled\" orders"]"#), "{}", out);
+    assert!(out.contains("bill -> invoices\n"));
+}
+
+#[test]
+fn weights_emitted_on_edges() {
+    let graph = parse_graph(r#"
+data:
+  - {name: orders, source: s}
+  - {name: invoices, source: s}
+functions:
+  - name: bill
+    owner: o
+    inputs: [{name: orders, weight: 5}]
+    outputs: [invoices]
+"#).unwrap();
+
+    let (_, edges) = summarise_dot(&render_to_string(&graph, &RenderOptions::default()));
+    assert!(edges.contains(&("orders".into(), "bill".into(), r#"{"weight": "5"}"#.into())));
+    assert!(edges.contains(&("bill".into(), "invoices".into(), "{}".into())));
+}
+
+#[test]
+fn edge_ports() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - name: f
+    owner: o
+    inputs:
+      - {name: a, port: old, function_port: n}
+    outputs:
+      - {name: a, port: new}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions {
+        data_shape: Some(Shape::Record),
+        ..Default::default()
+    });
+    assert!(out.contains("a:old -> f:n"));
+    assert!(out.contains("f -> a:new"));
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["a"]["label"], r#""{a|{<new> new|<old> old}}""#);
+    assert!(!nodes["f"].contains_key("label"));
+
+    let err = parse_graph(r#"
+data: []
+functions:
+  - {name: f, owner: o, inputs: [{name: a, port: "bad port!"}], outputs: []}
+"#).unwrap_err().to_string();
+    assert!(err.contains("invalid port 'bad port!', expected an identifier"), "{}", err);
+}
+
+#[test]
+fn ports_drawn_for_inputs_and_outputs() {
+    let graph = parse_graph(r#"
+data:
+  - {name: orders, source: s}
+  - {name: "price|list", source: s}
+  - {name: invoices, source: s}
+functions:
+  - {name: bill, owner: o, inputs: [orders, "price|list"], outputs: [invoices]}
+  - {name: archive, owner: o, inputs: [invoices], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { ports: true, ..Default::default() });
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes["bill"]["shape"], "record");
+    assert_eq!(nodes["bill"]["label"], r#""{{<in0> orders|<in1> price\|list}|bill|{<out0> invoices}}""#);
+    assert_eq!(nodes["archive"]["label"], r#""{{<in0> invoices}|archive}""#);
+    assert!(out.contains("orders -> bill:in0"), "{}", out);
+    assert!(out.contains("\"price|list\" -> bill:in1"), "{}", out);
+    assert!(out.contains("bill:out0 -> invoices"), "{}", out);
+    assert_eq!(edges.len(), 4);
+}
+
+#[test]
+fn record_labels_escape_field_separators() {
+    assert_eq!(record_label("a|b", std::iter::empty()), r#""a\|b""#);
+    assert_eq!(record_label("a", vec!["n", "p"].into_iter()), r#""{a|{<p> p}}""#);
+}
+
+#[test]
+fn grouped_edges_follow_nodes() {
+    let graph = parse_graph(include_str!("sample.yaml")).unwrap();
+
+    for compact in &[false, true] {
+        let opts = RenderOptions { compact: *compact, ..Default::default() };
+        let grouped = render_to_string(&graph, &RenderOptions { group_edges: true, ..opts.clone() });
+        assert_eq!(summarise_dot(&grouped), summarise_dot(&render_to_string(&graph, &opts)));
+
+        let body = &grouped[..grouped.find("subgraph cluster_legend").unwrap()];
+        let lines: Vec<&str> = body.lines().collect();
+        let first_edge = lines.iter().position(|l| l.contains("->")).unwrap();
+        assert!(lines[first_edge..].iter().all(|l| l.contains("->")));
+        let edges = &lines[first_edge..];
+        assert!(edges.windows(2).all(|w| w[0] <= w[1]), "edges should be sorted: {:?}", edges);
+    }
+}
+
+#[test]
+fn duplicate_functions_merged() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+functions:
+  - {name: copy, owner: o, inputs: [a, b], outputs: [b]}
+  - {name: other, owner: p, inputs: [a, b], outputs: [b]}
+  - {name: paste, owner: o, inputs: [b, a], outputs: [b]}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { merge_duplicates: true, ..Default::default() });
+    let (nodes, edges) = summarise_dot(&out);
+    assert_eq!(nodes["copy"]["label"], "\"copy, paste\"");
+    assert!(!nodes.contains_key("paste"));
+    assert!(!nodes["other"].contains_key("label"));
+    assert_eq!(edges.iter().filter(|(from, _, _)| from == "a").count(), 2);
+
+    let (nodes, _) = summarise_dot(&render_to_string(&graph, &RenderOptions::default()));
+    assert!(nodes.contains_key("paste"));
+}
+
+#[test]
+fn nested_owner_clusters() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: ingest, owner: platform/ingest, inputs: [], outputs: [a]}
+  - {name: enrich, owner: platform/enrich, inputs: [a], outputs: []}
+  - {name: report, owner: analytics, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions { cluster_owners: true, ..Default::default() });
+    assert!(out.contains("subgraph cluster_analytics {\nlabel=\"analytics\"\nreport\n}"));
+    assert!(out.contains(concat!(
+        "subgraph cluster_platform {\nlabel=\"platform\"\n",
+        "subgraph cluster_platform_enrich {\nlabel=\"enrich\"\nenrich\n}\n",
+        "subgraph cluster_platform_ingest {\nlabel=\"ingest\"\ningest\n}\n",
+        "}"
+    )));
+}
+
+#[test]
+fn component_clusters() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: x, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: [b]}
+  - {name: g, owner: o, inputs: [x], outputs: []}
+"#).unwrap();
+    let opts = RenderOptions { label_components: true, ..Default::default() };
+
+    let out = render_to_string(&graph, &opts);
+    assert!(out.contains("subgraph cluster_component_1 {\nlabel=\"Component 1\"\na\nb\nf\n}"));
+    assert!(out.contains("subgraph cluster_component_2 {\nlabel=\"Component 2\"\nx\ng\n}"));
+    assert!(!out.contains("cluster_component_3"));
+
+    let connected = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a], outputs: []}
+"#).unwrap();
+    assert_eq!(
+        render_to_string(&connected, &opts),
+        render_to_string(&connected, &RenderOptions::default())
+    );
+}
+
+#[test]
+fn owner_annotations() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: Serve, inputs: [a], outputs: []}
+  - {name: g, owner: Ingest, inputs: [a], outputs: []}
+  - {name: h, owner: Serve, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions::default());
+    assert!(!out.contains("//"));
+
+    for compact in &[false, true] {
+        let out = render_to_string(&graph, &RenderOptions {
+            annotate_owners: true,
+            compact: *compact,
+            ..Default::default()
+        });
+        let comments: Vec<&str> = out.lines().filter(|l| l.starts_with("//")).collect();
+        assert_eq!(comments, vec!["// owner: Ingest", "// owner: Serve"]);
+
+        let plain = render_to_string(&graph, &RenderOptions {
+            compact: *compact,
+            ..Default::default()
+        });
+        assert_eq!(summarise_dot(&out), summarise_dot(&plain));
+    }
+}
+
+#[test]
+fn colorblind_safe_palette() {
+    let graph = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: Alpha, inputs: [a], outputs: []}
+  - {name: g, owner: Beta, inputs: [a], outputs: []}
+  - {name: h, owner: Gamma, inputs: [a], outputs: []}
+"#).unwrap();
+
+    let out = render_to_string(&graph, &RenderOptions {
+        palette: Palette::Scheme(ColorScheme::OkabeIto),
+        ..Default::default()
+    });
+    let (nodes, _) = summarise_dot(&out);
+    assert_eq!(nodes["f"]["fillcolor"], r##""#E69F00""##);
+    assert_eq!(nodes["g"]["fillcolor"], r##""#56B4E9""##);
+    assert_eq!(nodes["h"]["fillcolor"], r##""#009E73""##);
+    assert_eq!(nodes["legend_Beta"]["fillcolor"], r##""#56B4E9""##);
+}
+
+#[cfg(test)]
+fn render_to_string(graph: &Graph, opts: &RenderOptions) -> String {
+    let mut out = Vec::new();
+    render(&mut out, graph, opts).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// Node declarations, with any `node[...]` defaults applied, and edges
+/// of a DOT graph as emitted by `render`, for comparing the semantics of
+/// different renderings.
+#[cfg(test)]
+type DotSummary = (
+    BTreeMap<String, BTreeMap<String, String>>,
+    std::collections::BTreeSet<(String, String, String)>,
+);
+
+#[cfg(test)]
+fn summarise_dot(dot: &str) -> DotSummary {
+    fn parse_attrs(attrs: &str) -> BTreeMap<String, String> {
+        // Split on commas outside of quoted strings.
+        let mut parts = vec![String::new()];
+        let (mut quoted, mut escaped) = (false, false);
+        for c in attrs.chars() {
+            match c {
+                ',' if !quoted => parts.push(String::new()),
+                _ => parts.last_mut().unwrap().push(c),
+            }
+            quoted ^= c == '"' && !escaped;
+            escaped = c == '\\' && !escaped;
+        }
+        parts
+            .iter()
+            .filter(|a| !a.is_empty())
+            .map(|a| {
+                let mut kv = a.splitn(2, '=');
+                (kv.next().unwrap().to_string(), kv.next().unwrap().to_string())
+            })
+            .collect()
+    }
+
+    let mut scopes = vec![BTreeMap::new()];
+    let mut nodes = BTreeMap::new();
+    let mut edges = std::collections::BTreeSet::new();
+    for line in dot.lines().map(|l| l.trim().trim_end_matches(';')) {
+        if line.starts_with("//") {
+            continue;
+        }
+        let (stmt, attrs) = match line.find('[') {
+            Some(i) => (&line[..i], parse_attrs(&line[i + 1..line.len() - 1])),
+            None => (line, BTreeMap::new()),
+        };
+        if stmt.ends_with('{') {
+            let current = scopes.last().unwrap().clone();
+            scopes.push(current);
+        } else if stmt == "}" {
+            scopes.pop();
+        } else if stmt == "node" {
+            scopes.last_mut().unwrap().extend(attrs);
+        } else if stmt.contains("->") {
+            let ids: Vec<&str> = stmt.split("->").map(|s| s.trim()).collect();
+            for pair in ids.windows(2) {
+                edges.insert((pair[0].into(), pair[1].into(), format!("{:?}", attrs)));
+            }
+        } else if !stmt.contains('=') {
+            let mut effective = scopes.last().unwrap().clone();
+            effective.extend(attrs);
+            nodes.insert(stmt.to_string(), effective);
+        }
+    }
+    (nodes, edges)
+}
+
+#[test]
+fn compact_output_renders_same_graph() {
+    let graph: Graph = serde_yaml::from_str(r#"
+data:
+  - name: Events
+    source: Player
+  - name: Position
+    source: GameLoop
+    url: https://example.com
+functions:
+  - name: Update
+    owner: Game
+    inputs: [Events, Position]
+    outputs: [Position]
+  - name: Draw
+    owner: Renderer
+    inputs: [Position]
+    outputs: []
+  - name: Record
+    owner: Game
+    inputs: [Events]
+    outputs: []
+"#).unwrap();
+
+    let normal = render_to_string(&graph, &RenderOptions::default());
+    let compact = render_to_string(&graph, &RenderOptions {
+        compact: true,
+        ..Default::default()
+    });
+
+    assert!(compact.len() < normal.len());
+    assert!(compact.contains("node[shape=box];"));
+    assert!(!compact.contains(" -> "));
+    let (nodes, edges) = summarise_dot(&normal);
+    assert_eq!(nodes.len(), 7);
+    assert_eq!(edges.len(), 6);
+    assert_eq!(summarise_dot(&compact), (nodes, edges));
+}
+
+/// A dataflow graph.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Graph {
+    data: Vec<Data>,
+    functions: Vec<Function>,
+    /// Colours for particular owners, e.g. to match a team's branding,
+    /// overriding those assigned from the palette.
+    #[serde(default, deserialize_with = "deserialize_legend", skip_serializing_if = "HashMap::is_empty")]
+    legend: HashMap<String, String>,
+    /// Explanations to draw on the diagram.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<Note>,
+}
+
+/// An explanation drawn on a dataflow diagram, in a box of its own.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Note {
+    /// The text of the note.
+    text: String,
+    /// The name of the data or function that the note is about, which it's
+    /// linked to by a dashed line. The note isn't drawn if this node isn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    near: Option<String>,
+}
+
+/// A piece of data in a dataflow graph.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Data {
+    /// The name of this data, as shown on the
+    /// rendered diagram.
+    name: String,
+    /// The name of the application or service that maintains
+    /// or provides this data.
+    source: String,
+    /// Human-readable description of this data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// Link to further information about this data, e.g. a dashboard.
+    /// Rendered SVG nodes become hyperlinks to this location.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// Position of this node, as "x,y" in points, for fixed layouts
+    /// rendered with `neato -n`.
+    #[serde(default, deserialize_with = "deserialize_pos", skip_serializing_if = "Option::is_none")]
+    pos: Option<String>,
+    /// The part this data plays in the graph, if it's notable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<Role>,
+    /// How up to date this data is kept, shown by its fill colour.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    freshness: Option<Freshness>,
+    /// A measure of this data, e.g. its row count, shown by --heatmap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metric: Option<f64>,
+    /// Further Graphviz attributes for this node, e.g. `fontsize: "20"`,
+    /// for anything not otherwise supported. Attributes set by flux take
+    /// precedence over these.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    attributes: HashMap<String, String>,
+}
+
+/// The role of a piece of data in a dataflow graph.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Role {
+    /// A final output of the graph, which isn't expected to be consumed.
+    Sink,
+}
+
+/// How up to date a piece of data is kept. Data are filled with paler
+/// shades of green the less often they're updated, and stale data are
+/// filled with orange so that they stand out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Freshness {
+    Realtime,
+    Hourly,
+    Daily,
+    Stale,
+}
+
+impl Freshness {
+    fn fill_colour(self) -> &'static str {
+        match self {
+            Freshness::Realtime => "#74c476",
+            Freshness::Hourly => "#a1d99b",
+            Freshness::Daily => "#e5f5e0",
+            Freshness::Stale => "#fdae6b",
+        }
+    }
+}
+
+/// A process in a dataflow graph, i.e. a function.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Function {
+    /// The name of this function, as shown on the
+    /// rendered diagram.
+    name: String,
+    /// The process or service which performs this process.
+    owner: String,
+    /// Human-readable description of this function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// Inputs to this function. To render a graph, each input needs
+    /// to have a corresponding Data instance. These can also be given
+    /// as a comma-separated string of names, e.g. "orders, prices".
+    #[serde(with = "flow_list")]
+    #[schemars(with = "flow_list::FlowList<Flow>")]
+    inputs: Vec<Flow>,
+    /// Outputs from this function. To render a graph, each output needs
+    /// to have a corresponding Data instance.
+    #[serde(with = "flow_list")]
+    #[schemars(with = "flow_list::FlowList<Flow>")]
+    outputs: Vec<Flow>,
+    /// Link to further information about this function, e.g. a runbook.
+    /// Rendered SVG nodes become hyperlinks to this location.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// Position of this node, as "x,y" in points, for fixed layouts
+    /// rendered with `neato -n`.
+    #[serde(default, deserialize_with = "deserialize_pos", skip_serializing_if = "Option::is_none")]
+    pos: Option<String>,
+    /// Set to false to document a function in the spec without rendering
+    /// it, e.g. for a planned stage that hasn't been built yet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    enabled: Option<bool>,
+    /// Labels for cross-cutting concerns, e.g. "pii" or "realtime", which
+    /// can be used to select functions to render.
+    #[serde(default, deserialize_with = "null_as_default", skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// A measure of this function, e.g. its cost or latency, shown by
+    /// --heatmap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metric: Option<f64>,
+    /// Further Graphviz attributes for this node, as for `Data::attributes`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    attributes: HashMap<String, String>,
+}
+
+/// Deserializes an optional node position, which must be of the form
+/// "x,y" for numbers x and y, optionally followed by "!" to pin the node.
+fn deserialize_pos<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    let pos: Option<String> = Option::deserialize(d)?;
+    if let Some(pos) = &pos {
+        let coords: Vec<&str> = pos.trim_end_matches('!').split(',').collect();
+        if coords.len() != 2 || coords.iter().any(|c| c.trim().parse::<f64>().is_err()) {
+            return Err(serde::de::Error::custom(
+                format!("invalid position '{}', expected x,y", pos)
+            ));
+        }
+    }
+    Ok(pos)
+}
+
+/// Deserializes a map from owners to colours, each of which must be hex or
+/// a colour name.
+fn deserialize_legend<'de, D: serde::Deserializer<'de>>(d: D) -> Result<HashMap<String, String>, D::Error> {
+    let legend: HashMap<String, String> = HashMap::deserialize(d)?;
+    for (owner, color) in &legend {
+        check_colour(color).map_err(|e| {
+            serde::de::Error::custom(format!("invalid legend colour for '{}': {}", owner, e))
+        })?;
+    }
+    Ok(legend)
+}
+
+/// Deserializes a missing or null value as the default for its type.
+fn null_as_default<'de, D, T>(d: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::deserialize(d)?.unwrap_or_default())
+}
+
+impl Function {
+    fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+}
+
+/// A flow of data into or out of a function. In a spec this is either
+/// just the name of the data, or a map with the name and further details.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Flow {
+    /// The name of the data flowing into or out of the function.
+    name: String,
+    /// The nature of the flow, e.g. "read", "write" or "event". This
+    /// determines the arrowhead used on the rendered edge.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+    /// The port on the data node at which the edge attaches. This is either
+    /// a compass point such as "n" or "se", or the name of a field when the
+    /// data is drawn as a record, which lets several flows between the same
+    /// nodes be told apart.
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_port")]
+    port: Option<String>,
+    /// The port on the function node at which the edge attaches.
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_port")]
+    function_port: Option<String>,
+    /// The expected latency of the flow, e.g. "p99: 50ms", which labels
+    /// the rendered edge.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency: Option<String>,
+    /// How strongly Graphviz should keep the edge short and straight,
+    /// relative to the default weight of 1, e.g. for a critical flow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weight: Option<u32>,
+    /// Further details of the flow, e.g. why it exists, shown when hovering
+    /// over the edge in rendered SVG.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    /// The format in which the data is transferred, e.g. "avro" or "csv",
+    /// which labels the rendered edge after any latency.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<String>,
+}
+
+/// Deserializes an optional port name, which must be an identifier so
+/// that it can be used unquoted both on edges and in record labels.
+fn deserialize_port<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    let port: Option<String> = Option::deserialize(d)?;
+    if let Some(port) = &port {
+        let valid = port.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && port.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(serde::de::Error::custom(
+                format!("invalid port '{}', expected an identifier", port)
+            ));
+        }
+    }
+    Ok(port)
+}
+
+impl Flow {
+    /// True if this flow has no details beyond the data name.
+    fn is_bare(&self) -> bool {
+        *self == Flow { name: self.name.clone(), ..Default::default() }
+    }
+}
+
+/// (De)serialization of lists of flows, writing bare flows as strings.
+mod flow_list {
+    use super::Flow;
+    use schemars::JsonSchema;
+    use serde::de::{self, MapAccess, SeqAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+
+    /// Either the name of the data, or a map with the name and further details.
+    #[derive(Serialize, JsonSchema)]
+    #[serde(untagged)]
+    pub enum FlowForm<T> {
+        Name(String),
+        Detailed(T),
+    }
+
+    pub fn serialize<S: Serializer>(flows: &[Flow], s: S) -> Result<S::Ok, S::Error> {
+        let forms: Vec<FlowForm<&Flow>> = flows
+            .iter()
+            .map(|f| if f.is_bare() { FlowForm::Name(f.name.clone()) } else { FlowForm::Detailed(f) })
+            .collect();
+        forms.serialize(s)
+    }
+
+    // This only describes the schema. Lists are deserialized by
+    // `FlowListVisitor`, as an untagged enum would hide the errors in each
+    // flow behind one generic message.
+    /// Either a list of flows, or a single string of comma-separated data
+    /// names, e.g. "orders, prices".
+    #[derive(JsonSchema)]
+    #[serde(untagged)]
+    #[allow(dead_code)]
+    pub enum FlowList<T> {
+        List(Vec<FlowForm<T>>),
+        Names(String),
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Flow>, D::Error> {
+        d.deserialize_any(FlowListVisitor)
+    }
+
+    struct FlowListVisitor;
+
+    impl<'de> Visitor<'de> for FlowListVisitor {
+        type Value = Vec<Flow>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a list of flows, or a string of comma-separated data names")
+        }
+
+        fn visit_str<E: de::Error>(self, names: &str) -> Result<Vec<Flow>, E> {
+            Ok(names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| Flow { name: name.to_string(), ..Default::default() })
+                .collect())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<Flow>, A::Error> {
+            let mut flows = Vec::new();
+            while let Some(FlowElement(flow)) = seq.next_element()? {
+                flows.push(flow);
+            }
+            Ok(flows)
+        }
+    }
+
+    /// A single flow in a list, read as either form of `FlowForm`.
+    struct FlowElement(Flow);
+
+    impl<'de> Deserialize<'de> for FlowElement {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            d.deserialize_any(FlowVisitor).map(FlowElement)
+        }
+    }
+
+    struct FlowVisitor;
+
+    impl<'de> Visitor<'de> for FlowVisitor {
+        type Value = Flow;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("the name of a data, or a map with the name and further details")
+        }
+
+        fn visit_str<E: de::Error>(self, name: &str) -> Result<Flow, E> {
+            Ok(Flow { name: name.to_string(), ..Default::default() })
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Flow, A::Error> {
+            Flow::deserialize(de::value::MapAccessDeserializer::new(map))
+        }
+    }
+}
+
+#[test]
+fn flows_read_from_comma_separated_names() {
+    let list = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: f, owner: o, inputs: [a, b], outputs: [c]}
+"#).unwrap();
+    let names = parse_graph(r#"
+data:
+  - {name: a, source: s}
+  - {name: b, source: s}
+  - {name: c, source: s}
+functions:
+  - {name: f, owner: o, inputs: "a, b", outputs: c}
+"#).unwrap();
+
+    assert_eq!(list, names);
+    assert!(parse_graph("data: []\nfunctions: [{name: f, owner: o, inputs: \"\", outputs: []}]").unwrap()
+        .functions[0].inputs.is_empty());
+}
+
+#[test]
+fn flow_errors_kept() {
+    let err = parse_graph(r#"
+data: []
+functions:
+  - name: f
+    owner: o
+    inputs: [a, {name: b, weight: heavy}]
+    outputs: []
+"#).unwrap_err().to_string();
+    assert!(err.contains("invalid type: string \"heavy\", expected u32"), "{}", err);
+    assert!(err.contains("line 6"), "{}", err);
+
+    let err = parse_graph("data: []\nfunctions: [{name: f, owner: o, inputs: 3, outputs: []}]").unwrap_err();
+    assert!(err.to_string().contains("expected a list of flows, or a string of comma-separated data names"), "{}", err);
+}
+
+/// JSON Schema for the `data` and `functions` form of a spec.
+fn spec_schema() -> String {
+    let schema = schemars::schema_for!(Graph);
+    serde_json::to_string_pretty(&schema).expect("schemas can always be serialized")
+}
+
+#[test]
+fn schema_marks_optional_fields() {
+    let schema: serde_json::Value = serde_json::from_str(&spec_schema()).unwrap();
+    let required = |def: &str| -> Vec<String> {
+        serde_json::from_value(schema["definitions"][def]["required"].clone()).unwrap()
+    };
+    let function = required("Function");
+    for field in &["name", "owner", "inputs", "outputs"] {
+        assert!(function.contains(&field.to_string()), "{} should be required", field);
+    }
+    assert!(!function.contains(&"tags".to_string()));
+    let data = required("Data");
+    assert!(data.contains(&"name".to_string()));
+    assert!(!data.contains(&"description".to_string()));
+    assert!(schema["definitions"]["Data"]["properties"]["description"].is_object());
+}
+
+/// The top level of a spec, which either has `data` and `functions` lists
+/// as in `Graph`, or a single `nodes` list with each entry tagged with its
+/// type, e.g. `{type: data, name: Position, source: GameLoop}`. All keys are
+/// optional here so that either form can be deserialized in a single pass.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    data: Option<Vec<Data>>,
+    functions: Option<Vec<Function>>,
+    nodes: Option<Vec<FlatNode>>,
+    #[serde(default, deserialize_with = "deserialize_legend")]
+    legend: HashMap<String, String>,
+    #[serde(default)]
+    notes: Vec<Note>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum FlatNode {
+    Data(Data),
+    Function(Function),
+}
+
+impl Spec {
+    fn into_graph(self) -> Result<Graph, serde_yaml::Error> {
+        use serde::de::Error;
+
+        let nodes = match self.nodes {
+            Some(nodes) => nodes,
+            None => {
+                return Ok(Graph {
+                    data: self.data.ok_or_else(|| Error::missing_field("data"))?,
+                    functions: self.functions.ok_or_else(|| Error::missing_field("functions"))?,
+                    legend: self.legend,
+                    notes: self.notes,
+                });
+            }
+        };
+
+        let mut graph = Graph {
+            data: self.data.unwrap_or_default(),
+            functions: self.functions.unwrap_or_default(),
+            legend: self.legend,
+            notes: self.notes,
+        };
+        for node in nodes {
+            match node {
+                FlatNode::Data(d) => graph.data.push(d),
+                FlatNode::Function(f) => graph.functions.push(f),
+            }
+        }
+        Ok(graph)
+    }
+}
+
+/// Parses a dataflow specification. See `Spec` for the accepted formats.
+#[cfg(test)]
+fn parse_graph(content: &str) -> Result<Graph, serde_yaml::Error> {
+    parse_graph_reader(content.as_bytes())
+}
+
+/// Parses a dataflow specification directly from `reader`, without first
+/// reading it into a `String` or an intermediate YAML value. If there are
+/// several YAML documents, separated by `---`, their graphs are merged as
+/// by `merge_graphs`.
+#[cfg(test)]
+fn parse_graph_reader<R: std::io::Read>(reader: R) -> Result<Graph, serde_yaml::Error> {
+    Ok(merge_fragments(parse_fragments_reader(reader)?))
+}
+
+/// Parses the graph of each YAML document read from `reader`. There is
+/// always at least one.
+fn parse_fragments_reader<R: std::io::Read>(reader: R) -> Result<Vec<Graph>, serde_yaml::Error> {
+    let mut graphs = Vec::new();
+    for document in serde_yaml::Deserializer::from_reader(reader) {
+        graphs.push(Spec::deserialize(document)?.into_graph()?);
+    }
+    if graphs.is_empty() {
+        // Gives the same error as parsing an empty document.
+        graphs.push(serde_yaml::from_str::<Spec>("")?.into_graph()?);
+    }
+    Ok(graphs)
+}
+
+/// Merges the graphs of the documents of a spec, as `merge_graphs` does.
+fn merge_fragments(mut fragments: Vec<Graph>) -> Graph {
+    if fragments.len() == 1 {
+        fragments.pop().unwrap()
+    } else {
+        merge_graphs(&fragments)
+    }
+}
+
+#[test]
+fn documents_merged() {
+    let graph = parse_graph(r#"
+data:
+  - {name: events, source: Web}
+  - {name: sessions, source: Ingest}
+functions:
+  - {name: sessionise, owner: Ingest, inputs: [events], outputs: [sessions]}
+---
+data:
+  - {name: sessions, source: Ingest}
+  - {name: report, source: Reporting}
+functions:
+  - {name: summarise, owner: Reporting, inputs: [sessions], outputs: [report]}
+"#).unwrap();
+
+    let data: Vec<&str> = graph.data.iter().map(|d| d.name.as_str()).collect();
+    let functions: Vec<&str> = graph.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(data, vec!["events", "sessions", "report"]);
+    assert_eq!(functions, vec!["sessionise", "summarise"]);
+
+    let err = parse_graph("data: []\nfunctions: []\n---\ndata: []\n").unwrap_err();
+    assert!(err.to_string().contains("missing field `functions`"), "{}", err);
+}
+
+#[test]
+fn unknown_fields_rejected() {
+    let err = parse_graph("data: []\nfunction: []\n").unwrap_err().to_string();
+    assert!(err.contains("unknown field `function`"), "{}", err);
+    assert!(err.contains("`functions`"), "{}", err);
+
+    let err = parse_graph(r#"
+data:
+  - {name: a, source: s, descripton: typo}
+functions: []
+"#).unwrap_err().to_string();
+    assert!(err.contains("unknown field `descripton`"), "{}", err);
+
+    let err = parse_graph(r#"
+data:
+  - {name: a, source: s}
+functions:
+  - {name: f, owner: o, inputs: [{name: a, prot: x}], outputs: []}
+"#).unwrap_err().to_string();
+    assert!(err.contains("unknown field `prot`"), "{}", err);
+
+    let err = parse_graph(r#"
+nodes:
+  - {type: function, name: f, owner: o, inputs: [], outputs: [], tag: [x]}
+"#).unwrap_err().to_string();
+    assert!(err.contains("unknown field `tag`"), "{}", err);
+
+    assert!(parse_graph(r#"
+nodes:
+  - {type: data, name: a, source: s, pos: "1,2"}
+  - {type: function, name: f, owner: o, inputs: [a], outputs: [], enabled: false}
+"#).is_ok());
+}
+
+#[test]
+fn large_spec_parses_from_reader() {
+    use std::fmt::Write;
+
+    const N: usize = 5000;
+    let mut spec = String::from("data:\n");
+    for i in 0..=N {
+        writeln!(spec, "  - {{name: d{}, source: s{}}}", i, i % 7).unwrap();
+    }
+    spec.push_str("functions:\n");
+    for i in 0..N {
+        writeln!(
+            spec,
+            "  - {{name: f{}, owner: o{}, inputs: [d{}], outputs: [d{}]}}",
+            i, i % 5, i, i + 1
+        ).unwrap();
+    }
+
+    let graph = parse_graph_reader(std::io::Cursor::new(spec.into_bytes())).unwrap();
+    assert_eq!(graph.data.len(), N + 1);
+    assert_eq!(graph.functions.len(), N);
+    assert_eq!(graph.functions[N - 1].outputs[0].name, format!("d{}", N));
+}
+
+#[test]
+fn split_spec_requires_both_lists() {
+    let err = parse_graph("data: []").unwrap_err();
+    assert!(err.to_string().contains("missing field `functions`"));
+}
+
+#[test]
+fn flat_spec_matches_split_spec() {
+    let split = parse_graph(r#"
+data:
+  - name: Events
+    source: Player
+  - name: Position
+    source: GameLoop
+functions:
+  - name: Update
+    owner: Game
+    inputs: [Events]
+    outputs: [Position]
+"#).unwrap();
+
+    let flat = parse_graph(r#"
+nodes:
+  - type: data
+    name: Events
+    source: Player
+  - type: function
+    name: Update
+    owner: Game
+    inputs: [Events]
+    outputs: [Position]
+  - type: data
+    name: Position
+    source: GameLoop
+"#).unwrap();
+
+    assert_eq!(flat, split);
 }
@@ -0,0 +1,56 @@
+//! Timing of the phases of a run, for finding out where the time goes
+//! when rendering large specs.
+
+use std::io::{Result, Write};
+use std::time::{Duration, Instant};
+
+/// The total time spent in each phase, in the order the phases started.
+pub struct Profile {
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile { phases: Vec::new() }
+    }
+
+    /// Runs `f`, adding the time it takes to the total for `phase`.
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.add(phase, start.elapsed());
+        result
+    }
+
+    /// Adds `elapsed` to the total for `phase`.
+    pub fn add(&mut self, phase: &'static str, elapsed: Duration) {
+        match self.phases.iter_mut().find(|(p, _)| *p == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => self.phases.push((phase, elapsed)),
+        }
+    }
+
+    /// Writes one line per phase, giving its total time in milliseconds.
+    pub fn report<W: Write>(&self, w: &mut W) -> Result<()> {
+        for (phase, total) in &self.phases {
+            writeln!(w, "{}: {:.3}ms", phase, total.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn phases_are_reported_in_order_with_totals() {
+    let mut profile = Profile::new();
+    assert_eq!(profile.time("parsing", || 1), 1);
+    profile.add("rendering", Duration::from_millis(2));
+    profile.add("parsing", Duration::from_millis(3));
+
+    let mut out = Vec::new();
+    profile.report(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("parsing: 3."));
+    assert_eq!(lines[1], "rendering: 2.000ms");
+}
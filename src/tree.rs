@@ -0,0 +1,160 @@
+//! Rendering of dataflow graphs as indented ASCII trees, for reading
+//! in a terminal.
+
+use crate::{Function, Graph, Renderer};
+use std::collections::{HashMap, HashSet};
+use std::io::{Result, Write};
+
+/// Renders graphs as trees, as described for `render_tree`.
+pub struct TreeRenderer;
+
+impl Renderer for TreeRenderer {
+    fn render(&self, mut w: &mut dyn Write, graph: &Graph) -> Result<()> {
+        render_tree(&mut w, graph)
+    }
+}
+
+/// Renders `graph` as a tree rooted at its external inputs, with each data
+/// followed by the functions consuming it and each function by its outputs.
+/// Data and functions reachable in more than one way are only expanded at
+/// their first occurrence, and are marked "(see above)" thereafter.
+pub fn render_tree<W: Write>(w: &mut W, graph: &Graph) -> Result<()> {
+    let functions: Vec<&Function> = graph.functions.iter().filter(|f| f.is_enabled()).collect();
+    let mut consumers: HashMap<&str, Vec<&Function>> = HashMap::new();
+    for f in &functions {
+        for input in &f.inputs {
+            consumers.entry(&input.name).or_default().push(f);
+        }
+    }
+
+    let roots = crate::analysis::external_inputs(graph);
+    let mut tree = Tree { consumers, expanded: HashSet::new() };
+
+    for root in &roots {
+        tree.data(w, root, "", None)?;
+    }
+    for f in functions.iter().filter(|f| f.inputs.is_empty()) {
+        tree.function(w, f, "", None)?;
+    }
+    // Anything not yet reached is only reachable from a cycle, or not
+    // referenced by any function.
+    for d in &graph.data {
+        if !tree.expanded.contains(&Item::Data(&d.name)) {
+            tree.data(w, &d.name, "", None)?;
+        }
+    }
+    for f in &functions {
+        if !tree.expanded.contains(&Item::Function(&f.name)) {
+            tree.function(w, f, "", None)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Item<'a> {
+    Data(&'a str),
+    Function(&'a str),
+}
+
+struct Tree<'a> {
+    consumers: HashMap<&'a str, Vec<&'a Function>>,
+    expanded: HashSet<Item<'a>>,
+}
+
+impl<'a> Tree<'a> {
+    /// Writes a line for `name`. `last` is None for roots, and otherwise
+    /// whether this is the last child of its parent. Returns the prefix for
+    /// children of this line, or None if it has already been expanded.
+    fn line<W: Write>(
+        &mut self,
+        w: &mut W,
+        item: Item<'a>,
+        name: &str,
+        prefix: &str,
+        last: Option<bool>
+    ) -> Result<Option<String>> {
+        let (branch, indent) = match last {
+            None => ("", ""),
+            Some(false) => ("|-- ", "|   "),
+            Some(true) => ("`-- ", "    "),
+        };
+        if self.expanded.insert(item) {
+            writeln!(w, "{}{}{}", prefix, branch, name)?;
+            Ok(Some(format!("{}{}", prefix, indent)))
+        } else {
+            writeln!(w, "{}{}{} (see above)", prefix, branch, name)?;
+            Ok(None)
+        }
+    }
+
+    fn data<W: Write>(&mut self, w: &mut W, name: &'a str, prefix: &str, last: Option<bool>) -> Result<()> {
+        if let Some(prefix) = self.line(w, Item::Data(name), name, prefix, last)? {
+            let consumers = self.consumers.get(name).cloned().unwrap_or_default();
+            for (i, f) in consumers.iter().enumerate() {
+                self.function(w, f, &prefix, Some(i + 1 == consumers.len()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn function<W: Write>(&mut self, w: &mut W, f: &'a Function, prefix: &str, last: Option<bool>) -> Result<()> {
+        if let Some(prefix) = self.line(w, Item::Function(&f.name), &f.name, prefix, last)? {
+            for (i, o) in f.outputs.iter().enumerate() {
+                self.data(w, &o.name, &prefix, Some(i + 1 == f.outputs.len()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn tree_to_string(graph: &Graph) -> String {
+    let mut out = Vec::new();
+    render_tree(&mut out, graph).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn tree_of_linear_pipeline() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: clicks, source: Browser}
+  - {name: sessions, source: Pipeline}
+  - {name: summary, source: Pipeline}
+  - {name: alerts, source: Pipeline}
+functions:
+  - {name: sessionise, owner: Pipeline, inputs: [clicks], outputs: [sessions]}
+  - {name: summarise, owner: Pipeline, inputs: [sessions], outputs: [summary, alerts]}
+"#).unwrap();
+
+    assert_eq!(tree_to_string(&graph), "\
+clicks
+`-- sessionise
+    `-- sessions
+        `-- summarise
+            |-- summary
+            `-- alerts
+");
+}
+
+#[test]
+fn tree_references_shared_subtrees_and_cycles() {
+    let graph = crate::parse_graph(r#"
+data:
+  - {name: a, source: Upstream}
+  - {name: b, source: Upstream}
+  - {name: state, source: Pipeline}
+functions:
+  - {name: join, owner: Pipeline, inputs: [a, b, state], outputs: [state]}
+"#).unwrap();
+
+    assert_eq!(tree_to_string(&graph), "\
+a
+`-- join
+    `-- state
+        `-- join (see above)
+b
+`-- join (see above)
+");
+}
@@ -0,0 +1,117 @@
+use assert_cmd::Command;
+
+#[test]
+fn continue_on_error_summarises_failures() {
+    let dir = tempfile::tempdir().unwrap();
+    let spec = "data: [{name: a, source: s}]\nfunctions: [{name: f, owner: o, inputs: [a], outputs: []}]\n";
+    for name in &["one", "two", "three"] {
+        std::fs::write(dir.path().join(format!("{}.yaml", name)), spec).unwrap();
+    }
+    std::fs::write(dir.path().join("malformed.yaml"), "data: [\n").unwrap();
+    std::fs::write(
+        dir.path().join("undefined.yaml"),
+        "data: []\nfunctions: [{name: f, owner: o, inputs: [x], outputs: []}]\n"
+    ).unwrap();
+    let out = dir.path().join("out");
+
+    let output = Command::cargo_bin("flux")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["batch", "--continue-on-error", "-o", "out"])
+        .args(["one.yaml", "malformed.yaml", "two.yaml", "undefined.yaml", "three.yaml"])
+        .assert()
+        .failure()
+        .code(1);
+
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "3 ok, 2 failed");
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with("malformed.yaml: "), "{}", stdout);
+    assert!(lines[2].starts_with("undefined.yaml: ") && lines[2].contains("undefined data: x"), "{}", stdout);
+    for name in &["one", "two", "three"] {
+        assert!(out.join(format!("{}.dot", name)).exists());
+    }
+}
+
+#[test]
+fn batch_stops_at_first_error() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("malformed.yaml"), "data: [\n").unwrap();
+    std::fs::write(dir.path().join("valid.yaml"), "data: []\nfunctions: []\n").unwrap();
+
+    Command::cargo_bin("flux")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["batch", "-o", "out", "malformed.yaml", "valid.yaml"])
+        .assert()
+        .failure()
+        .code(2);
+    assert!(!dir.path().join("out").join("valid.dot").exists());
+}
+
+#[test]
+fn parallel_jobs_keep_summary_in_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut inputs = Vec::new();
+    for i in 0..10 {
+        let name = format!("spec{}.yaml", i);
+        let spec = if i % 3 == 0 {
+            "data: [\n".to_string()
+        } else {
+            format!("data: [{{name: d{0}, source: s}}]\nfunctions: [{{name: f{0}, owner: o, inputs: [d{0}], outputs: []}}]\n", i)
+        };
+        std::fs::write(dir.path().join(&name), spec).unwrap();
+        inputs.push(name);
+    }
+
+    let output = Command::cargo_bin("flux")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["batch", "--continue-on-error", "--jobs", "4", "-o", "out"])
+        .args(&inputs)
+        .assert()
+        .failure()
+        .code(1);
+
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "6 ok, 4 failed");
+    let failed: Vec<&str> = lines[1..].iter().map(|l| l.split(':').next().unwrap()).collect();
+    assert_eq!(failed, vec!["spec0.yaml", "spec3.yaml", "spec6.yaml", "spec9.yaml"]);
+    for i in (0..10).filter(|i| i % 3 != 0) {
+        let dot = std::fs::read_to_string(dir.path().join("out").join(format!("spec{}.dot", i))).unwrap();
+        assert!(dot.contains(&format!("d{} -> f{}", i, i)), "{}", dot);
+    }
+}
+
+#[test]
+fn output_names_keep_dots_and_must_be_distinct() {
+    let dir = tempfile::tempdir().unwrap();
+    let spec = "data: []\nfunctions: []\n";
+    for path in &["a/x.yaml", "b/x.yaml", "orders.v2.yaml"] {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, spec).unwrap();
+    }
+
+    let output = Command::cargo_bin("flux")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["batch", "-o", "out", "orders.v2.yaml", "a/x.yaml", "b/x.yaml"])
+        .assert()
+        .failure()
+        .code(1);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("'a/x.yaml' and 'b/x.yaml' would both be written to 'out/x.dot'"), "{}", stderr);
+    assert!(!dir.path().join("out").exists());
+
+    Command::cargo_bin("flux")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["batch", "-o", "out", "orders.v2.yaml", "a/x.yaml"])
+        .assert()
+        .success();
+    assert!(dir.path().join("out").join("orders.v2.dot").exists());
+    assert!(dir.path().join("out").join("x.dot").exists());
+}
@@ -0,0 +1,143 @@
+use assert_cmd::Command;
+
+/// Runs flux on `spec`, read from stdin, with any further `args`.
+fn flux(spec: &str, args: &[&str]) -> assert_cmd::assert::Assert {
+    Command::cargo_bin("flux")
+        .unwrap()
+        .args(["-i", "-"])
+        .args(args)
+        .write_stdin(spec)
+        .assert()
+}
+
+#[test]
+fn malformed_spec_exits_with_parse_code() {
+    flux("data: [\n", &[]).failure().code(2);
+}
+
+#[test]
+fn undefined_data_exits_with_validation_code() {
+    flux("data: []\nfunctions: [{name: f, owner: o, inputs: [x], outputs: []}]\n", &[])
+        .failure()
+        .code(3);
+}
+
+#[test]
+fn strict_lint_exits_with_lint_code() {
+    let spec = "data: [{name: a, source: s}]\nfunctions: [{name: f, owner: o, inputs: [a], outputs: [a]}]\n";
+    flux(spec, &["--strict", "--warn-self-dependencies"]).failure().code(4);
+}
+
+#[test]
+fn valid_spec_succeeds() {
+    flux("data: [{name: a, source: s}]\nfunctions: [{name: f, owner: o, inputs: [a], outputs: []}]\n", &[])
+        .success();
+}
+
+#[test]
+fn orphan_data_fails_assert_no_orphans() {
+    let spec = "data: [{name: a, source: s}, {name: stale, source: s}]\n\
+                functions: [{name: f, owner: o, inputs: [a], outputs: []}]\n";
+    flux(spec, &[]).success();
+    let output = flux(spec, &["--assert-no-orphans"]).failure().code(4);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("data not used by any function: stale;"), "{}", stderr);
+}
+
+#[test]
+fn single_producer_assertion() {
+    let spec = "data: [{name: a, source: s}, {name: b, source: s}]\n\
+                functions: [{name: f, owner: o, inputs: [a], outputs: [b]}]\n";
+    flux(spec, &["--assert-single-producer"]).success();
+    flux(spec, &["--assert-single-producer", "--assert-has-producer"]).failure().code(4);
+
+    let spec = "data: [{name: a, source: s}, {name: b, source: s}]\n\
+                functions: [{name: f, owner: o, inputs: [a], outputs: [b]}, \
+                            {name: g, owner: o, inputs: [a], outputs: [b]}]\n";
+    let output = flux(spec, &["--assert-single-producer"]).failure().code(4);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("data with more than one producer: b (produced by f, g)"), "{}", stderr);
+}
+
+#[test]
+fn unlisted_owner_fails_allowed_owners() {
+    let mut allowlist = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut allowlist, b"# approved\nBilling\n\n").unwrap();
+    let path = allowlist.path().to_str().unwrap();
+
+    let spec = "data: [{name: a, source: s}]\n\
+                functions: [{name: f, owner: Billing, inputs: [a], outputs: []}, \
+                            {name: g, owner: Biling, inputs: [a], outputs: []}]\n";
+    let output = flux(spec, &["--allowed-owners", path]).failure().code(4);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("g (owner 'Biling')"), "{}", stderr);
+    assert!(!stderr.contains("f (owner"), "{}", stderr);
+}
+
+#[test]
+fn duplicate_input_exits_with_validation_code() {
+    let spec = "data: [{name: a, source: s}]\nfunctions: [{name: f, owner: o, inputs: [a, a], outputs: []}]\n";
+    let output = flux(spec, &[]).failure().code(3);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("f lists input a more than once"), "{}", stderr);
+
+    let output = flux(spec, &["--dedupe-flows"]).success();
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("warning: ignoring duplicate flows"), "{}", stderr);
+}
+
+#[test]
+fn disconnected_graph_fails_assert_connected() {
+    let spec = "data: [{name: a, source: s}, {name: b, source: s}]\n\
+                functions: [{name: f, owner: o, inputs: [a], outputs: [b]}, \
+                            {name: g, owner: o, inputs: [b], outputs: []}]\n";
+    flux(spec, &["--assert-connected"]).success();
+
+    let spec = "data: [{name: a, source: s}, {name: b, source: s}, {name: c, source: s}]\n\
+                functions: [{name: f, owner: o, inputs: [a], outputs: [b]}, \
+                            {name: g, owner: o, inputs: [c], outputs: []}]\n";
+    let output = flux(spec, &["--assert-connected"]).failure().code(4);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("graph has 2 disconnected parts, one containing each of: a, c"), "{}", stderr);
+}
+
+#[test]
+fn blank_fields_exit_with_validation_code() {
+    let spec = "data: [{name: a, source: s}]\nfunctions: [{name: f, owner: \"  \", inputs: [a], outputs: []}]\n";
+    let output = flux(spec, &[]).failure().code(3);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("functions[0] has an empty owner"), "{}", stderr);
+
+    let spec = "data: [{name: a, source: \" \"}]\nfunctions: [{name: f, owner: o, inputs: [a], outputs: []}]\n";
+    flux(spec, &[]).success();
+    let output = flux(spec, &["--strict-schema"]).failure().code(3);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("data[0] (a) has an empty source"), "{}", stderr);
+}
+
+#[test]
+fn duplicate_names_fail_require_unique_names() {
+    let spec = "data: [{name: a, source: s}]\n\
+                functions: [{name: load, owner: Search, inputs: [a], outputs: []}, \
+                            {name: load, owner: Billing, inputs: [a], outputs: []}]\n";
+    let output = flux(spec, &[]).success();
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains("\"Search/load\"") && stdout.contains("\"Billing/load\""), "{}", stdout);
+    let output = flux(spec, &["--require-unique-names"]).failure().code(4);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("names used more than once: load (owners Search, Billing)"), "{}", stderr);
+}
+
+#[test]
+fn limits_exit_with_lint_code() {
+    let spec = "data: [{name: a, source: s}, {name: b, source: s}]\n\
+                functions: [{name: f, owner: o, inputs: [a], outputs: [b]}, \
+                            {name: g, owner: o, inputs: [b], outputs: []}]\n";
+    flux(spec, &["--max-depth", "2", "--max-edges", "3"]).success();
+    let output = flux(spec, &["--max-depth", "1"]).failure().code(4);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("chain of 2 functions exceeds maximum depth of 1"), "{}", stderr);
+    let output = flux(spec, &["--max-edges", "2"]).failure().code(4);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("graph has 3 edges, more than the maximum of 2"), "{}", stderr);
+}
@@ -0,0 +1,19 @@
+use assert_cmd::Command;
+
+#[test]
+fn explain_gives_owner_and_colour_of_function() {
+    let output = Command::cargo_bin("flux")
+        .unwrap()
+        .args(["-i", "-", "--explain"])
+        .write_stdin("data: [{name: a, source: s}]\nfunctions: [{name: enrich, owner: billing, inputs: [a], outputs: []}]\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.lines().any(|l| l == "function \"enrich\": owner=billing -> color /dark28/1; shape=ellipse"),
+        "{}", stderr
+    );
+    assert!(stderr.lines().any(|l| l == "data \"a\": shape=box"), "{}", stderr);
+}
@@ -0,0 +1,31 @@
+use assert_cmd::Command;
+
+#[test]
+fn profile_reports_each_phase() {
+    let output = Command::cargo_bin("flux")
+        .unwrap()
+        .args(["-i", "-", "--profile"])
+        .write_stdin("data: [{name: a, source: s}]\nfunctions: [{name: f, owner: o, inputs: [a], outputs: []}]\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for phase in &["parsing", "validation", "colour assignment", "rendering"] {
+        assert!(
+            stderr.lines().any(|l| l.starts_with(&format!("{}: ", phase))),
+            "no entry for {} in {}", phase, stderr
+        );
+    }
+}
+
+#[test]
+fn no_profile_by_default() {
+    Command::cargo_bin("flux")
+        .unwrap()
+        .args(["-i", "-"])
+        .write_stdin("data: [{name: a, source: s}]\nfunctions: []\n")
+        .assert()
+        .success()
+        .stderr("");
+}
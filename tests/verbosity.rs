@@ -0,0 +1,34 @@
+use assert_cmd::Command;
+
+const SPEC: &str = "data: [{name: a, source: s}]\nfunctions: [{name: f, owner: o, inputs: [a], outputs: []}]\n";
+
+/// Runs flux on `SPEC` with `args`, returning its stderr.
+fn stderr(args: &[&str]) -> String {
+    let output = Command::cargo_bin("flux")
+        .unwrap()
+        .args(["-i", "-"])
+        .args(args)
+        .write_stdin(SPEC)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn very_verbose_logs_parsing() {
+    let stderr = stderr(&["-vv"]);
+    assert!(stderr.contains("parsed 1 functions and 1 data"), "{}", stderr);
+}
+
+#[test]
+fn quiet_by_default() {
+    assert_eq!(stderr(&[]), "");
+}
+
+#[test]
+fn verbose_outlines_run() {
+    let stderr = stderr(&["-v"]);
+    assert!(stderr.contains("rendering 1 functions and 1 data"), "{}", stderr);
+    assert!(!stderr.contains("parsed"), "{}", stderr);
+}